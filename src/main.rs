@@ -1,11 +1,25 @@
-mod wakeboy;
+use wakeboy_i::wakeboy;
 
 use structopt::StructOpt;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use wakeboy::core::*;
 use wakeboy::cpu::*;
+use wakeboy::cartridge::*;
+use wakeboy::header::*;
+use wakeboy::rtc::*;
+use wakeboy::ppu::Renderer;
+use wakeboy::screenshot::{self, Palette, Trigger};
+use wakeboy::save;
 use colored::*;
 
+/// M-cycles in one frame, the emulation is run frame by frame
+const FRAME_CYCLES: u64 = 17556;
+/// How often battery-backed ram gets written to disk while running
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "wakeboy-i")]
 struct Opt {
@@ -13,7 +27,7 @@ struct Opt {
     #[structopt(short, long, parse(from_os_str), default_value = "__none")]
     input: PathBuf,
     
-    /// Enable debug tools [NOT IMPLEMENTED]
+    /// Print every instruction as it runs
     #[structopt(short, long)]
     debug: bool,
 
@@ -24,6 +38,51 @@ struct Opt {
     /// Path to an alternative boot rom (should be 256 bytes long)
     #[structopt(short, long, default_value = "__none")]
     boot_rom: String,
+
+    /// Don't run a boot rom, start at 0x0100 in the state it would have left
+    #[structopt(long)]
+    skip_boot: bool,
+
+    /// What the cartridge clock follows: host (wall time) or emulated (CPU cycles)
+    #[structopt(long, default_value = "host")]
+    rtc: RtcClock,
+
+    /// How lines get drawn: scanline (fast) or fifo (shows mid-line register changes)
+    #[structopt(long, default_value = "scanline")]
+    renderer: Renderer,
+
+    /// Directory where battery saves are kept, defaults to the rom's directory
+    #[structopt(long, parse(from_os_str))]
+    save_dir: Option<PathBuf>,
+
+    /// Write the screen to this PNG file and exit, once --screenshot-frames or --screenshot-pc is reached
+    #[structopt(long, parse(from_os_str))]
+    screenshot: Option<PathBuf>,
+
+    /// Take the screenshot after this many frames
+    #[structopt(long)]
+    screenshot_frames: Option<u64>,
+
+    /// Take the screenshot when the CPU gets to this address (hexadecimal)
+    #[structopt(long, parse(try_from_str = parse_address))]
+    screenshot_pc: Option<u16>,
+
+    /// Screenshot colors: grey or green
+    #[structopt(long, default_value = "grey")]
+    palette: Palette,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Print the cartridge header of the rom and validate its checksums
+    Info,
+}
+
+fn parse_address(s: &str) -> Result<u16, std::num::ParseIntError> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16)
 }
 
 fn get_path() -> std::io::Result<PathBuf> {
@@ -33,6 +92,46 @@ fn get_path() -> std::io::Result<PathBuf> {
     Ok(dir)
 }
 
+fn print_info(rom: &[u8]) {
+    let header = match CartridgeHeader::parse(rom) {
+        Some(h) => h,
+        None => {
+            println!("{}", "Error: Rom is too small to contain a header".red());
+            std::process::exit(0);
+        }
+    };
+
+    let format_size = |size: Option<usize>| match size {
+        Some(s) => format!("{} KiB", s / 1024),
+        None => "Unknown".to_owned(),
+    };
+
+    println!("Title:              {}", header.title);
+    println!("Manufacturer code:  {}", header.manufacturer_code);
+    println!("CGB flag:           {:#04x}", header.cgb_flag);
+    println!("SGB flag:           {:#04x}", header.sgb_flag);
+    println!("Cartridge type:     {:#04x} ({})", header.cartridge_type, header.cartridge_type_name());
+    println!("Rom size:           {:#04x} ({})", header.rom_size, format_size(header.rom_size_bytes()));
+    println!("Ram size:           {:#04x} ({})", header.ram_size, format_size(header.ram_size_bytes()));
+    println!("Destination code:   {:#04x}", header.destination_code);
+    println!("Licensee:           {}", header.licensee());
+    println!("Version:            {:#04x}", header.version);
+
+    let header_checksum = CartridgeHeader::compute_header_checksum(rom);
+    if header_checksum == header.header_checksum {
+        println!("Header checksum:    {:#04x} {}", header.header_checksum, "OK".green());
+    } else {
+        warn_or_crash(format!("Header checksum is {:#04x} but the header says {:#04x}", header_checksum, header.header_checksum));
+    }
+
+    let global_checksum = CartridgeHeader::compute_global_checksum(rom);
+    if global_checksum == header.global_checksum {
+        println!("Global checksum:    {:#06x} {}", header.global_checksum, "OK".green());
+    } else {
+        warn_or_crash(format!("Global checksum is {:#06x} but the header says {:#06x}", global_checksum, header.global_checksum));
+    }
+}
+
 fn main() {
     let mut opt = Opt::from_args();
 
@@ -52,21 +151,6 @@ fn main() {
                                     "default_rom/infinite_loop.bin"));
     }
 
-    let boot_rom: Vec<u8> = match read_rom(&opt.boot_rom) {
-        Some(b) => b,
-        None => {
-            println!("{} ({}) {}", "Error: Boot rom file".red(), opt.boot_rom, "wasn't found or is empty".red());
-            std::process::exit(0);
-        }
-    };
-
-    let len = boot_rom.len();
-    if len < 256 {
-        warn_or_crash(format!("Boot rom is smaller than 256 bytes\n{} bytes will be filled with 0's", 256 - len));
-    } else if len > 256 {
-        warn_or_crash(format!("Boot rom is larger than 256 bytes and will so be truncated\n{} bytes will be discarded", len - 256));
-    }
-
     let rom: Vec<u8> = match opt.input.to_str() {
         Some(a) => {
             match read_rom(&String::from(a)) {
@@ -80,8 +164,70 @@ fn main() {
         None => panic!("Invalid input"),
     };
 
-    let mut cpu: CPU = Default::default();
-    cpu.memory.load_boot_rom(&boot_rom[..256]);
+    if let Some(Command::Info) = opt.command {
+        print_info(&rom);
+        return;
+    }
+
+    let mut cpu = CPU { debug: opt.debug, ..Default::default() };
+    cpu.memory.ppu.set_renderer(opt.renderer);
+
+    if opt.skip_boot {
+        cpu.skip_boot();
+    } else {
+        let boot_rom: Vec<u8> = match read_rom(&opt.boot_rom) {
+            Some(b) => b,
+            None => {
+                println!("{} ({}) {}", "Error: Boot rom file".red(), opt.boot_rom, "wasn't found or is empty, try --skip-boot".red());
+                std::process::exit(0);
+            }
+        };
+
+        let len = boot_rom.len();
+        if len < 256 {
+            warn_or_crash(format!("Boot rom is smaller than 256 bytes\n{} bytes will be filled with 0's", 256 - len));
+        } else if len > 256 {
+            warn_or_crash(format!("Boot rom is larger than 256 bytes and will so be truncated\n{} bytes will be discarded", len - 256));
+        }
+
+        cpu.memory.load_boot_rom(&boot_rom);
+    }
+
+    let mut cartridge = Cartridge::new(rom, opt.rtc);
+    cartridge.set_rumble_handler(Box::new(|on| {
+        println!("Rumble motor turned {}", if on { "on" } else { "off" });
+    }));
+    let save_path = save::save_path(&opt.input, opt.save_dir.as_deref());
+    save::load(&mut cartridge, &save_path);
+    cpu.memory.load_cartridge(cartridge);
+
+    if let Some(path) = &opt.screenshot {
+        let trigger = Trigger { frames: opt.screenshot_frames, pc: opt.screenshot_pc };
+        if trigger.frames.is_none() && trigger.pc.is_none() {
+            println!("{}", "Error: --screenshot needs --screenshot-frames or --screenshot-pc".red());
+            std::process::exit(0);
+        }
+
+        if let Err(e) = screenshot::capture(&mut cpu, &trigger, path, opt.palette) {
+            warn_or_crash(format!("Couldn't write screenshot {} ({})", path.display(), e));
+        }
+        save::write(&mut cpu.memory.cartridge, &save_path);
+        return;
+    }
+
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || r.store(false, Ordering::SeqCst)).expect("Couldn't set the Ctrl-C handler");
+
+    let mut last_save = Instant::now();
+    while running.load(Ordering::SeqCst) {
+        cpu.run_for(FRAME_CYCLES);
+
+        if last_save.elapsed() >= SAVE_INTERVAL {
+            save::write(&mut cpu.memory.cartridge, &save_path);
+            last_save = Instant::now();
+        }
+    }
 
-    cpu.run();
+    save::write(&mut cpu.memory.cartridge, &save_path);
 }
@@ -0,0 +1,153 @@
+use super::core::*;
+use super::flags::*;
+use super::aluops::*;
+
+/// Applies an 8-bit ALU operation to A and an operand
+/// Returns the new value of A (unchanged for CP) and the resulting flags
+pub fn apply(operation: Operation, a: u8, value: u8, carry: bool) -> (u8, RegFlags) {
+	let carry = carry as u8;
+	let mut flags = RegFlags::default();
+
+	let result = match operation {
+		Operation::ADD | Operation::ADC => {
+			let carry = if let Operation::ADC = operation { carry } else { 0 };
+			let result = a as u16 + value as u16 + carry as u16;
+			flags.h = (a & 0x0F) + (value & 0x0F) + carry > 0x0F;
+			flags.c = result > 0xFF;
+			result as u8
+		},
+		Operation::SUB | Operation::SBC | Operation::CP => {
+			let carry = if let Operation::SBC = operation { carry } else { 0 };
+			let result = (a as i16) - (value as i16) - (carry as i16);
+			flags.n = true;
+			flags.h = ((a & 0x0F) as i16) - ((value & 0x0F) as i16) - (carry as i16) < 0;
+			flags.c = result < 0;
+			result as u8
+		},
+		Operation::AND => {
+			flags.h = true;
+			a & value
+		},
+		Operation::XOR => a ^ value,
+		Operation::OR => a | value,
+		Operation::Invalid => {
+			warn_or_crash(String::from("Invalid ALU operation"));
+			a
+		},
+	};
+
+	flags.z = result == 0;
+	if let Operation::CP = operation {
+		return (a, flags)
+	}
+	(result, flags)
+}
+
+/// ADD HL,rr: H is the carry out of bit 11, C out of bit 15, Z is left untouched
+pub fn add_hl(hl: u16, value: u16, flags: RegFlags) -> (u16, RegFlags) {
+	let (result, carry) = hl.overflowing_add(value);
+	let flags = RegFlags {
+		z: flags.z,
+		n: false,
+		h: (hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF,
+		c: carry,
+	};
+	(result, flags)
+}
+
+/// ADD SP,e and LD HL,SP+e: the offset is signed, but H and C
+/// come from the unsigned addition of the low byte
+pub fn add_sp(sp: u16, e: u8) -> (u16, RegFlags) {
+	let result = sp.wrapping_add(e as i8 as u16);
+	let flags = RegFlags {
+		z: false,
+		n: false,
+		h: (sp & 0x0F) + (e as u16 & 0x0F) > 0x0F,
+		c: (sp & 0xFF) + (e as u16) > 0xFF,
+	};
+	(result, flags)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn flags(z: bool, n: bool, h: bool, c: bool) -> u8 {
+		u8::from(RegFlags { z, n, h, c })
+	}
+
+	#[test]
+	fn add_half_carry() {
+		let (result, f) = apply(Operation::ADD, 0x0F, 0x01, false);
+		assert_eq!(result, 0x10);
+		assert_eq!(u8::from(f), flags(false, false, true, false));
+	}
+
+	#[test]
+	fn add_carry_to_zero() {
+		let (result, f) = apply(Operation::ADC, 0xFE, 0x01, true);
+		assert_eq!(result, 0x00);
+		assert_eq!(u8::from(f), flags(true, false, true, true));
+	}
+
+	#[test]
+	fn sub_borrow() {
+		let (result, f) = apply(Operation::SUB, 0x10, 0x01, true);
+		assert_eq!(result, 0x0F);
+		assert_eq!(u8::from(f), flags(false, true, true, false));
+
+		let (result, f) = apply(Operation::SUB, 0x00, 0x01, false);
+		assert_eq!(result, 0xFF);
+		assert_eq!(u8::from(f), flags(false, true, true, true));
+	}
+
+	#[test]
+	fn sbc_borrow_from_carry_in() {
+		let (result, f) = apply(Operation::SBC, 0x10, 0x00, true);
+		assert_eq!(result, 0x0F);
+		assert_eq!(u8::from(f), flags(false, true, true, false));
+
+		let (result, f) = apply(Operation::SBC, 0x01, 0x01, true);
+		assert_eq!(result, 0xFF);
+		assert_eq!(u8::from(f), flags(false, true, true, true));
+	}
+
+	#[test]
+	fn cp_ignores_carry_in_and_keeps_a() {
+		let (result, f) = apply(Operation::CP, 0x42, 0x42, true);
+		assert_eq!(result, 0x42);
+		assert_eq!(u8::from(f), flags(true, true, false, false));
+
+		let (result, f) = apply(Operation::CP, 0x10, 0x21, false);
+		assert_eq!(result, 0x10);
+		assert_eq!(u8::from(f), flags(false, true, true, true));
+	}
+
+	#[test]
+	fn and_sets_half_carry() {
+		let (result, f) = apply(Operation::AND, 0xF0, 0x0F, true);
+		assert_eq!(result, 0x00);
+		assert_eq!(u8::from(f), flags(true, false, true, false));
+	}
+
+	#[test]
+	fn add_hl_keeps_zero() {
+		let before = RegFlags { z: true, n: true, h: false, c: false };
+		let (result, f) = add_hl(0x0FFF, 0x0001, before);
+		assert_eq!(result, 0x1000);
+		assert_eq!(u8::from(f), flags(true, false, true, false));
+	}
+
+	#[test]
+	fn add_sp_negative_offset() {
+		// 0xFFF8 + -1: both carries come out of the low byte
+		let (result, f) = add_sp(0xFFF8, 0xFF);
+		assert_eq!(result, 0xFFF7);
+		assert_eq!(u8::from(f), flags(false, false, true, true));
+
+		// No carry out of the low byte even though the high byte borrows
+		let (result, f) = add_sp(0x0100, 0xFE);
+		assert_eq!(result, 0x00FE);
+		assert_eq!(u8::from(f), flags(false, false, false, false));
+	}
+}
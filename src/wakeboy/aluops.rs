@@ -1,5 +1,3 @@
-use super::core::*;
-
 #[derive(Copy, Clone)]
 pub enum Operation {
 	ADD,
@@ -1,5 +1,13 @@
 use super::core::*;
+use super::interrupts::*;
+use super::cartridge::*;
+use super::timer::Timer;
+use super::io::{self, Peripheral};
+use super::dma::Dma;
+use super::ppu::{Ppu, Mode};
 
+const BOOT_ROM_BEGIN:		usize = 0x0000;
+const BOOT_ROM_END:			usize = 0x0100-1;
 const ROM_SPACE_BEGIN:		usize = 0x0000;
 const ROM_SPACE_END:		usize = 0x8000-1;
 const VRAM_BEGIN:			usize = 0x8000;
@@ -8,184 +16,402 @@ const EXTERNAL_RAM_BEGIN: 	usize = 0xA000;
 const EXTERNAL_RAM_END:		usize = 0xC000-1;
 const RAM_BEGIN: 			usize = 0xC000;
 const RAM_END: 				usize = 0xE000-1;
+const ECHO_RAM_BEGIN:		usize = 0xE000;
+const ECHO_RAM_END:			usize = 0xFE00-1;
 const OAM_RAM_BEGIN:		usize = 0xFE00;
 const OAM_RAM_END:			usize = 0xFEA0-1;
+const PROHIBITED_BEGIN:		usize = 0xFEA0;
+const PROHIBITED_END:		usize = 0xFF00-1;
 const IO_RAM_BEGIN:			usize = 0xFF00;
 const IO_RAM_END: 			usize = 0xFF80-1;
 const HRAM_BEGIN: 			usize = 0xFF80;
-const HRAM_END: 			usize = 0xFFFF;
+const HRAM_END: 			usize = 0xFFFF-1;
+const INTERRUPT_ENABLE:		usize = 0xFFFF;
+
+/// What the CPU reads when nothing drives the data bus
+pub const OPEN_BUS: u8 = 0xFF;
 
 pub struct MemoryBus {
-	rom_mem:	[u8; ROM_SPACE_END - ROM_SPACE_BEGIN + 1],
-	vram_mem:	[u8; VRAM_END - VRAM_BEGIN + 1],
-	extern_mem:	[u8; EXTERNAL_RAM_END - EXTERNAL_RAM_BEGIN + 1],
+	boot_rom:	[u8; BOOT_ROM_END - BOOT_ROM_BEGIN + 1],
+	/// The boot rom hides the start of the cartridge until 0xFF50 gets written to
+	boot_rom_mapped: bool,
+	pub cartridge: Cartridge,
 	ram_mem: 	[u8; RAM_END - RAM_BEGIN + 1],
-	oam_mem: 	[u8; OAM_RAM_END - OAM_RAM_BEGIN + 1],
 	io_ram_mem:	[u8; IO_RAM_END - IO_RAM_BEGIN + 1],
 	hram_mem: 	[u8; HRAM_END - HRAM_BEGIN + 1],
+	pub interrupts: InterruptController,
+	pub timer: Timer,
+	pub dma: Dma,
+	pub ppu: Ppu,
 }
 
 impl MemoryBus {
 
+	/// Advances the hardware hooked on the bus by `cycles` M-cycles
+	pub fn tick(&mut self, cycles: u32) {
+		for _ in 0..cycles {
+			self.timer.tick(&mut self.interrupts);
+			if let Some((source, index)) = self.dma.tick() {
+				self.ppu.oam[index] = self.read_mapped(source);
+			}
+			self.ppu.tick(&mut self.interrupts);
+		}
+		self.cartridge.tick(cycles);
+	}
+
 	pub fn get_imm8(&self, pc: &mut u16) -> u8 {
-		*pc += 1;
-		let ret = self.get_byte(*pc as usize);
-		ret.unwrap()
+		*pc = pc.wrapping_add(1);
+		self.get_byte(*pc as usize)
 	}
 
 	pub fn get_imm16(&self, pc: &mut u16) -> u16 {
-		*pc += 1;
+		*pc = pc.wrapping_add(1);
 		let ret = self.get_2bytes(*pc as usize);
-		*pc += 1;
-		ret.unwrap()
+		*pc = pc.wrapping_add(1);
+		ret
+	}
+
+	pub fn get_byte(&self, address: usize) -> u8 {
+		match self.dma.current_source() {
+			Some(source) if address < IO_RAM_BEGIN => {
+				// The CPU reads whatever the transfer puts on the bus it shares
+				// with it, and nothing at all from OAM
+				if address < OAM_RAM_BEGIN && is_vram_bus(address) == is_vram_bus(source) {
+					self.read_mapped(source)
+				} else {
+					OPEN_BUS
+				}
+			},
+			_ if self.is_locked(address) => OPEN_BUS,
+			_ => self.read_mapped(address),
+		}
+	}
+
+	/// Reads what's really there, without the DMA or PPU getting in the way.
+	/// For debug tools, the CPU goes through `get_byte`
+	pub fn peek(&self, address: usize) -> u8 {
+		self.read_mapped(address)
+	}
+
+	/// The PPU keeps VRAM to itself while drawing, and OAM from the OAM scan on.
+	/// The unusable area after OAM goes with it
+	fn is_locked(&self, address: usize) -> bool {
+		match address {
+			VRAM_BEGIN ..= VRAM_END => self.ppu.mode() == Mode::PixelTransfer,
+			OAM_RAM_BEGIN ..= OAM_RAM_END | PROHIBITED_BEGIN ..= PROHIBITED_END => matches!(self.ppu.mode(), Mode::OamScan | Mode::PixelTransfer),
+			_ => false,
+		}
 	}
 
-	pub fn get_byte(&self, address: usize) -> Option<u8> {
-		return match address {
+	fn read_mapped(&self, address: usize) -> u8 {
+		match address {
+			BOOT_ROM_BEGIN ..= BOOT_ROM_END if self.boot_rom_mapped => {
+				self.boot_rom[address]
+			},
 			ROM_SPACE_BEGIN ..= ROM_SPACE_END => {
-				Some(self.rom_mem[address])
+				self.cartridge.read_rom(address)
 			},
 			VRAM_BEGIN ..= VRAM_END => {
-				Some(self.vram_mem[address - VRAM_BEGIN])
+				self.ppu.vram[address - VRAM_BEGIN]
 			},
 			EXTERNAL_RAM_BEGIN ..= EXTERNAL_RAM_END => {
-				Some(self.extern_mem[address - EXTERNAL_RAM_BEGIN])
+				self.cartridge.read_ram(address - EXTERNAL_RAM_BEGIN)
 			},
 			RAM_BEGIN ..= RAM_END => {
-				Some(self.ram_mem[address - RAM_BEGIN])
+				self.ram_mem[address - RAM_BEGIN]
+			},
+			// Mirrors 0xC000-0xDDFF
+			ECHO_RAM_BEGIN ..= ECHO_RAM_END => {
+				self.ram_mem[address - ECHO_RAM_BEGIN]
 			},
 			OAM_RAM_BEGIN ..= OAM_RAM_END => {
-				Some(self.oam_mem[address - OAM_RAM_BEGIN])
+				self.ppu.oam[address - OAM_RAM_BEGIN]
+			},
+			// Reads as 0 on DMG
+			PROHIBITED_BEGIN ..= PROHIBITED_END => {
+				0x00
 			},
 			IO_RAM_BEGIN ..= IO_RAM_END => {
-				Some(self.io_ram_mem[address - IO_RAM_BEGIN])
+				self.read_io(address)
 			},
 			HRAM_BEGIN ..= HRAM_END => {
-				Some(self.hram_mem[address - HRAM_BEGIN])
+				self.hram_mem[address - HRAM_BEGIN]
+			},
+			INTERRUPT_ENABLE => {
+				self.interrupts.read_enable()
 			},
 			_ => {
 				warn_or_crash(String::from("CPU tried to access unassigned part of memory"));
-				None
+				OPEN_BUS
 			}
 		}
 	}
 
-	pub fn get_2bytes(&self, address: usize) -> Option<u16> {
-		return match address {
-			ROM_SPACE_BEGIN ..= ROM_SPACE_END => {
-				Some(combine_bytes( self.rom_mem[address], self.rom_mem[address + 1]))
-			},
-			VRAM_BEGIN ..= VRAM_END => {
-				Some(combine_bytes( self.vram_mem[address - VRAM_BEGIN],
-									self.vram_mem[address - VRAM_BEGIN + 1]))
-			},
-			EXTERNAL_RAM_BEGIN ..= EXTERNAL_RAM_END => {
-				Some(combine_bytes( self.extern_mem[address - EXTERNAL_RAM_BEGIN],
-									self.extern_mem[address - EXTERNAL_RAM_BEGIN + 1]))
-			},
-			RAM_BEGIN ..= RAM_END => {
-				Some(combine_bytes(	self.ram_mem[address - RAM_BEGIN],
-									self.ram_mem[address - RAM_BEGIN + 1]))
-			},
-			OAM_RAM_BEGIN ..= OAM_RAM_END => {
-				Some(combine_bytes( self.oam_mem[address - OAM_RAM_BEGIN],
-									self.oam_mem[address - OAM_RAM_BEGIN + 1]))
-			},
-			IO_RAM_BEGIN ..= IO_RAM_END => {
-				Some(combine_bytes( self.io_ram_mem[address - IO_RAM_BEGIN],
-									self.io_ram_mem[address - IO_RAM_BEGIN + 1]))
-			},
-			HRAM_BEGIN ..= HRAM_END => {
-				Some(combine_bytes( self.hram_mem[address - HRAM_BEGIN],
-									self.hram_mem[address - HRAM_BEGIN + 1]))
+	/// Hands the access to the peripheral owning the register, bits that
+	/// aren't readable read as 1
+	fn read_io(&self, address: usize) -> u8 {
+		let register = io::register(address);
+		let value = match register.peripheral {
+			Peripheral::Timer => self.timer.read(address),
+			Peripheral::Interrupts => self.interrupts.read_flag(),
+			// No buttons are ever pressed until input gets hooked up
+			Peripheral::Joypad => self.io_ram_mem[address - IO_RAM_BEGIN] | 0x0F,
+			Peripheral::Dma => self.dma.read(),
+			Peripheral::Lcd => self.ppu.read(address),
+			Peripheral::Unmapped => OPEN_BUS,
+			_ => self.io_ram_mem[address - IO_RAM_BEGIN],
+		};
+		register.read(value)
+	}
+
+	/// Peripherals only ever see the writable bits, registers without any are read-only
+	fn write_io(&mut self, address: usize, data: u8) {
+		let register = io::register(address);
+		if register.write_mask == 0 {
+			return
+		}
+
+		let data = data & register.write_mask;
+		match register.peripheral {
+			Peripheral::Timer => self.timer.write(address, data),
+			Peripheral::Interrupts => self.interrupts.write_flag(data),
+			Peripheral::Dma => self.dma.write(data),
+			Peripheral::Lcd => self.ppu.write(address, data, &mut self.interrupts),
+			// Once unmapped the boot rom can't be brought back
+			Peripheral::BootRom => {
+				if data != 0 {
+					self.boot_rom_mapped = false;
+				}
 			},
+			Peripheral::Unmapped => {},
 			_ => {
-				warn_or_crash(String::from("CPU tried to access unassigned part of memory"));
-				None
-			}
+				let current = &mut self.io_ram_mem[address - IO_RAM_BEGIN];
+				*current = register.merge(*current, data);
+			},
 		}
 	}
 
+	/// Little-endian, the high byte comes from the next address even when that
+	/// crosses into another region (or wraps from 0xFFFF to 0x0000)
+	pub fn get_2bytes(&self, address: usize) -> u16 {
+		let low = self.get_byte(address);
+		let high = self.get_byte(next_address(address));
+		combine_bytes(low, high)
+	}
+
+	/// Only I/O registers and HRAM can be written while an OAM DMA runs, and
+	/// writes to VRAM or OAM are lost while the PPU uses them
 	pub fn write_byte(&mut self, address: usize, data: u8) {
+		if (self.dma.is_active() && address < IO_RAM_BEGIN) || self.is_locked(address) {
+			return
+		}
+
 		match address {
 			ROM_SPACE_BEGIN ..= ROM_SPACE_END => {
-				self.rom_mem[address] = data;
+				self.cartridge.write_rom(address, data);
 			},
 			VRAM_BEGIN ..= VRAM_END => {
-				self.vram_mem[address - VRAM_BEGIN] = data;
+				self.ppu.vram[address - VRAM_BEGIN] = data;
 			},
 			EXTERNAL_RAM_BEGIN ..= EXTERNAL_RAM_END => {
-				self.extern_mem[address - EXTERNAL_RAM_BEGIN] = data;
+				self.cartridge.write_ram(address - EXTERNAL_RAM_BEGIN, data);
 			},
 			RAM_BEGIN ..= RAM_END => {
 				self.ram_mem[address - RAM_BEGIN] = data;
 			},
+			ECHO_RAM_BEGIN ..= ECHO_RAM_END => {
+				self.ram_mem[address - ECHO_RAM_BEGIN] = data;
+			},
 			OAM_RAM_BEGIN ..= OAM_RAM_END => {
-				self.oam_mem[address - OAM_RAM_BEGIN] = data;
+				self.ppu.oam[address - OAM_RAM_BEGIN] = data;
 			},
+			PROHIBITED_BEGIN ..= PROHIBITED_END => {},
 			IO_RAM_BEGIN ..= IO_RAM_END => {
-				self.io_ram_mem[address - IO_RAM_BEGIN] = data;
+				self.write_io(address, data);
 			},
 			HRAM_BEGIN ..= HRAM_END => {
 				self.hram_mem[address - HRAM_BEGIN] = data;
 			},
+			INTERRUPT_ENABLE => {
+				self.interrupts.write_enable(data);
+			},
 			_ => {
 				warn_or_crash(String::from("CPU tried to write on an unassigned part of memory"));
 			}
 		}
 	}
 
+	/// Low byte first, like LD (a16),SP. Pushes write the high byte first
+	/// and go through `CPU::push` instead
 	pub fn write_2bytes(&mut self, address: usize, data: u16) {
-		match address {
-			ROM_SPACE_BEGIN ..= ROM_SPACE_END => {
-				self.rom_mem[address] = (data << 8) as u8;
-				self.rom_mem[address + 1] = (data & 0xFF) as u8;
-			},
-			VRAM_BEGIN ..= VRAM_END => {
-				self.vram_mem[address - VRAM_BEGIN] = (data << 8) as u8;
-				self.vram_mem[address - VRAM_BEGIN + 1] = (data & 0xFF) as u8;
-			},
-			EXTERNAL_RAM_BEGIN ..= EXTERNAL_RAM_END => {
-				self.extern_mem[address - EXTERNAL_RAM_BEGIN] = (data << 8) as u8;
-				self.extern_mem[address - EXTERNAL_RAM_BEGIN + 1] = (data & 0xFF) as u8;
-			},
-			RAM_BEGIN ..= RAM_END => {
-				self.ram_mem[address - RAM_BEGIN] = (data << 8) as u8;
-				self.ram_mem[address - RAM_BEGIN + 1] = (data & 0xFF) as u8;
-			},
-			OAM_RAM_BEGIN ..= OAM_RAM_END => {
-				self.oam_mem[address - OAM_RAM_BEGIN] = (data << 8) as u8;
-				self.oam_mem[address - OAM_RAM_BEGIN + 1] = (data & 0xFF) as u8;
-			},
-			IO_RAM_BEGIN ..= IO_RAM_END => {
-				self.io_ram_mem[address - IO_RAM_BEGIN] = (data << 8) as u8;
-				self.io_ram_mem[address - IO_RAM_BEGIN + 1] = (data & 0xFF) as u8;
-			},
-			HRAM_BEGIN ..= HRAM_END => {
-				self.hram_mem[address - HRAM_BEGIN] = (data << 8) as u8;
-				self.hram_mem[address - HRAM_BEGIN + 1] = (data & 0xFF) as u8;
-			},
-			_ => {
-				warn_or_crash(String::from("CPU tried to write on an unassigned part of memory"));
-			},
-		}
+		self.write_byte(address, (data & 0xFF) as u8);
+		self.write_byte(next_address(address), (data >> 8) as u8);
+	}
+
+	pub fn load_cartridge(&mut self, cartridge: Cartridge) {
+		self.cartridge = cartridge;
 	}
 
+	/// Maps the boot rom over the cartridge, missing bytes are filled with 0's
 	pub fn load_boot_rom(&mut self, boot_rom: &[u8]) {
-		&self.rom_mem[..256].clone_from_slice(boot_rom);
+		let len = boot_rom.len().min(self.boot_rom.len());
+		self.boot_rom = [0; BOOT_ROM_END - BOOT_ROM_BEGIN + 1];
+		self.boot_rom[..len].clone_from_slice(&boot_rom[..len]);
+		self.boot_rom_mapped = true;
+	}
+
+	/// Puts the I/O registers in the state the boot rom leaves them in
+	pub fn skip_boot(&mut self) {
+		self.boot_rom_mapped = false;
+		self.timer.set_counter(0xABCC);
+		self.interrupts.write_flag(0xE1);
+		self.ppu.skip_boot();
+
+		// Stored as is, the CPU couldn't write the read-only bits of NR52
+		let sound_state: [(usize, u8); 15] = [
+			(0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF14, 0xBF),
+			(0xFF16, 0x3F), (0xFF19, 0xBF), (0xFF1A, 0x7F), (0xFF1B, 0xFF),
+			(0xFF1C, 0x9F), (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF23, 0xBF),
+			(0xFF24, 0x77), (0xFF25, 0xF3), (0xFF26, 0xF1),
+		];
+		for (address, data) in sound_state.iter() {
+			self.io_ram_mem[address - IO_RAM_BEGIN] = *data;
+		}
 	}
 }
 
 impl Default for MemoryBus {
 	fn default() -> Self {
 		MemoryBus {
-			rom_mem:	[0; ROM_SPACE_END - ROM_SPACE_BEGIN + 1],
-			vram_mem:	[0; VRAM_END - VRAM_BEGIN + 1],
-			extern_mem:	[0; EXTERNAL_RAM_END - EXTERNAL_RAM_BEGIN + 1],
+			boot_rom:	[0; BOOT_ROM_END - BOOT_ROM_BEGIN + 1],
+			boot_rom_mapped: false,
+			cartridge:	Default::default(),
 			ram_mem: 	[0; RAM_END - RAM_BEGIN + 1],
-			oam_mem: 	[0; OAM_RAM_END - OAM_RAM_BEGIN + 1],
 			io_ram_mem:	[0; IO_RAM_END - IO_RAM_BEGIN + 1],
 			hram_mem: 	[0; HRAM_END - HRAM_BEGIN + 1],
+			interrupts: Default::default(),
+			timer: Default::default(),
+			dma: Default::default(),
+			ppu: Default::default(),
+		}
+	}
+}
+
+/// VRAM sits on its own bus, everything else below OAM shares the cartridge bus
+fn is_vram_bus(address: usize) -> bool {
+	(VRAM_BEGIN ..= VRAM_END).contains(&address)
+}
+
+fn next_address(address: usize) -> usize {
+	(address + 1) & 0xFFFF
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::rtc::RtcClock;
+	use super::super::timer;
+
+	fn bus_with_rom(rom: Vec<u8>) -> MemoryBus {
+		let mut bus: MemoryBus = Default::default();
+		bus.load_cartridge(Cartridge::new(rom, RtcClock::Emulated));
+		bus
+	}
+
+	#[test]
+	fn two_bytes_are_little_endian() {
+		let mut bus: MemoryBus = Default::default();
+		bus.write_2bytes(0xC000, 0x1234);
+		assert_eq!(bus.get_byte(0xC000), 0x34);
+		assert_eq!(bus.get_byte(0xC001), 0x12);
+		assert_eq!(bus.get_2bytes(0xC000), 0x1234);
+	}
+
+	#[test]
+	fn read_across_rom_and_vram() {
+		let mut rom = vec![0; 0x8000];
+		rom[0x7FFF] = 0x34;
+		let mut bus = bus_with_rom(rom);
+		bus.write_byte(0x8000, 0x12);
+		assert_eq!(bus.get_2bytes(0x7FFF), 0x1234);
+	}
+
+	#[test]
+	fn write_across_ram_and_echo() {
+		let mut bus: MemoryBus = Default::default();
+		bus.write_2bytes(0xDFFF, 0xABCD);
+		assert_eq!(bus.get_byte(0xDFFF), 0xCD);
+		// 0xE000 mirrors 0xC000
+		assert_eq!(bus.get_byte(0xC000), 0xAB);
+		assert_eq!(bus.get_2bytes(0xDFFF), 0xABCD);
+	}
+
+	#[test]
+	fn access_across_io_and_hram() {
+		let mut bus: MemoryBus = Default::default();
+		// 0xFF7F is an unused I/O register, the write to it is lost
+		bus.write_2bytes(0xFF7F, 0x1234);
+		assert_eq!(bus.get_byte(0xFF80), 0x12);
+		assert_eq!(bus.get_2bytes(0xFF7F), 0x12FF);
+	}
+
+	#[test]
+	fn access_across_hram_and_ie() {
+		let mut bus: MemoryBus = Default::default();
+		bus.write_2bytes(0xFFFE, 0x1F42);
+		assert_eq!(bus.get_byte(0xFFFE), 0x42);
+		assert_eq!(bus.interrupts.read_enable(), 0x1F);
+		assert_eq!(bus.get_2bytes(0xFFFE), 0x1F42);
+	}
+
+	#[test]
+	fn access_wraps_from_ie_to_rom() {
+		let mut rom = vec![0; 0x8000];
+		rom[0x0000] = 0x12;
+		let mut bus = bus_with_rom(rom);
+		bus.write_2bytes(0xFFFF, 0x0B0A);
+		assert_eq!(bus.interrupts.read_enable(), 0x0A);
+		assert_eq!(bus.get_2bytes(0xFFFF), 0x120A);
+	}
+
+	#[test]
+	fn skip_boot_state_reads_back() {
+		let mut bus: MemoryBus = Default::default();
+		bus.skip_boot();
+		assert_eq!(bus.get_byte(io::NR52), 0xF1);
+		assert_eq!(bus.get_byte(io::NR11), 0xBF);
+		assert_eq!(bus.get_byte(io::LCDC), 0x91);
+		assert_eq!(bus.get_byte(io::BGP), 0xFC);
+		assert_eq!(bus.get_byte(io::IF), 0xE1);
+		assert_eq!(bus.get_byte(timer::DIV), 0xAB);
+	}
+
+	#[test]
+	fn io_writes_only_reach_writable_bits() {
+		let mut bus: MemoryBus = Default::default();
+		bus.write_byte(timer::TAC, 0xFD);
+		assert_eq!(bus.get_byte(timer::TAC), 0xFD);
+		bus.write_byte(timer::TAC, 0x02);
+		assert_eq!(bus.get_byte(timer::TAC), 0xFA);
+
+		let ly = bus.get_byte(io::LY);
+		bus.write_byte(io::LY, ly.wrapping_add(1));
+		assert_eq!(bus.get_byte(io::LY), ly);
+
+		let mode = bus.get_byte(io::STAT) & 0b11;
+		bus.write_byte(io::STAT, 0x00);
+		assert_eq!(bus.get_byte(io::STAT) & 0b1111_1011, 0x80 | mode);
+	}
+
+	#[test]
+	fn prohibited_area_is_locked_with_oam() {
+		let mut bus: MemoryBus = Default::default();
+		assert_eq!(bus.get_byte(PROHIBITED_BEGIN), 0x00);
+		bus.write_byte(io::LCDC, 0x80);
+		while bus.ppu.mode() != Mode::OamScan {
+			bus.ppu.tick(&mut bus.interrupts);
 		}
+		assert_eq!(bus.get_byte(PROHIBITED_BEGIN), OPEN_BUS);
+		assert_eq!(bus.get_byte(PROHIBITED_END), OPEN_BUS);
 	}
-}
\ No newline at end of file
+}
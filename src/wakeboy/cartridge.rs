@@ -0,0 +1,164 @@
+use super::core::*;
+use super::header::*;
+use super::mbc::{self, BankController, NoMbc, RumbleHandler};
+use super::rtc::*;
+
+const MIN_ROM_SIZE: usize = 0x8000;
+
+/// Contents of a cartridge, its rom is mapped into 0x0000-0x7FFF and its
+/// external ram into 0xA000-0xBFFF, both through the bank controller
+pub struct Cartridge {
+	rom: Vec<u8>,
+	ram: Vec<u8>,
+	mbc: Box<dyn BankController>,
+	battery: bool,
+	/// The ram was written to since the last save
+	ram_dirty: bool,
+}
+
+impl Cartridge {
+	/// Roms are always a power of two and at least 32 KiB long on real hardware,
+	/// anything else gets padded with 0xFF up to the next valid size
+	pub fn new(mut rom: Vec<u8>, rtc_clock: RtcClock) -> Self {
+		let len = rom.len();
+		if !len.is_power_of_two() || len < MIN_ROM_SIZE {
+			let size = len.next_power_of_two().max(MIN_ROM_SIZE);
+			warn_or_crash(format!("Rom size ({} bytes) isn't a power of two of at least {} bytes\n{} bytes will be filled with 0xFF's",
+				len, MIN_ROM_SIZE, size - len));
+			rom.resize(size, 0xFF);
+		}
+
+		let (mbc, ram_size, battery) = match CartridgeHeader::parse(&rom) {
+			Some(header) => {
+				let mbc = mbc::from_cartridge_type(header.cartridge_type, &rom, rtc_clock);
+				let ram_size = match (mbc.built_in_ram_size(), header.ram_size_bytes()) {
+					(Some(size), _) | (None, Some(size)) => size,
+					(None, None) => {
+						warn_or_crash(format!("Unknown ram size code ({:#04x}), the cartridge will have no ram", header.ram_size));
+						0
+					}
+				};
+				(mbc, ram_size, header.has_battery())
+			},
+			None => (Box::new(NoMbc) as Box<dyn BankController>, 0, false),
+		};
+
+		Cartridge {
+			rom,
+			ram: vec![0; ram_size],
+			mbc,
+			battery,
+			ram_dirty: false,
+		}
+	}
+
+	pub fn read_rom(&self, address: usize) -> u8 {
+		self.mbc.read_rom(&self.rom, address)
+	}
+
+	pub fn write_rom(&mut self, address: usize, data: u8) {
+		self.mbc.write_rom(address, data);
+	}
+
+	pub fn read_ram(&self, address: usize) -> u8 {
+		self.mbc.read_ram(&self.ram, address)
+	}
+
+	pub fn write_ram(&mut self, address: usize, data: u8) {
+		if self.mbc.write_ram(&mut self.ram, address, data) {
+			self.ram_dirty = true;
+		}
+	}
+
+	pub fn tick(&mut self, cycles: u32) {
+		self.mbc.tick(cycles);
+	}
+
+	pub fn set_rumble_handler(&mut self, handler: RumbleHandler) {
+		self.mbc.set_rumble_handler(handler);
+	}
+
+	/// Whether the ram (and clock) are kept alive by a battery, and should be saved
+	pub fn has_battery(&self) -> bool {
+		self.battery
+	}
+
+	/// Cartridges with a clock always have something new to save
+	pub fn needs_saving(&mut self) -> bool {
+		self.battery && (self.ram_dirty || self.mbc.rtc_mut().is_some())
+	}
+
+	/// Raw ram dump followed by the clock state if there is one,
+	/// the same layout as most other emulators' .sav files
+	pub fn save_data(&mut self) -> Vec<u8> {
+		self.ram_dirty = false;
+		let mut data = self.ram.clone();
+		if let Some(rtc) = self.mbc.rtc_mut() {
+			data.extend(rtc.save_state());
+		}
+		data
+	}
+
+	pub fn load_save_data(&mut self, data: &[u8]) {
+		let len = data.len().min(self.ram.len());
+		if len < self.ram.len() {
+			warn_or_crash(format!("Save file is smaller than the cartridge ram\n{} bytes will be left to 0", self.ram.len() - len));
+		}
+		self.ram[..len].copy_from_slice(&data[..len]);
+
+		let footer = &data[len..];
+		match self.mbc.rtc_mut() {
+			Some(rtc) if footer.len() == RTC_SAVE_SIZE || footer.len() == RTC_SAVE_SIZE_SHORT => rtc.load_state(footer),
+			_ if footer.is_empty() => {},
+			_ => warn_or_crash(format!("Save file has {} unexpected trailing bytes", footer.len())),
+		}
+	}
+}
+
+impl Default for Cartridge {
+	fn default() -> Self {
+		Cartridge {
+			rom: vec![0xFF; MIN_ROM_SIZE],
+			ram: Vec::new(),
+			mbc: Box::new(NoMbc),
+			battery: false,
+			ram_dirty: false,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// MBC1 with 8 KiB of battery-backed ram
+	fn battery_cartridge() -> Cartridge {
+		let mut rom = vec![0; MIN_ROM_SIZE];
+		rom[0x0147] = 0x03;
+		rom[0x0149] = 0x02;
+		Cartridge::new(rom, RtcClock::Emulated)
+	}
+
+	#[test]
+	fn only_stored_ram_writes_need_saving() {
+		let mut cartridge = battery_cartridge();
+		cartridge.write_ram(0x0000, 0x42);
+		assert!(!cartridge.needs_saving());
+
+		cartridge.write_rom(0x0000, 0x0A);
+		cartridge.write_ram(0x0000, 0x42);
+		assert!(cartridge.needs_saving());
+		cartridge.save_data();
+		assert!(!cartridge.needs_saving());
+	}
+
+	#[test]
+	fn writes_without_ram_dont_need_saving() {
+		let mut rom = vec![0; MIN_ROM_SIZE];
+		rom[0x0147] = 0x03;
+		let mut cartridge = Cartridge::new(rom, RtcClock::Emulated);
+		cartridge.write_rom(0x0000, 0x0A);
+		cartridge.write_ram(0x0000, 0x42);
+		assert!(!cartridge.needs_saving());
+	}
+}
@@ -2,43 +2,504 @@ use super::bus::*;
 use super::registers::*;
 use super::instructions::*;
 use super::core::*;
+use super::flags::*;
+use super::alu;
+use super::direction::*;
 
+#[derive(Default)]
 pub struct CPU {
 	pub memory: MemoryBus,
 	pub registers: Registers,
+	pub ime: bool,
+	/// Set by EI, IME only gets enabled once the following instruction has run
+	pub ime_scheduled: bool,
+	pub halted: bool,
+	/// HALT with IME=0 and an interrupt already pending doesn't halt,
+	/// instead the next opcode byte gets read twice
+	pub halt_bug: bool,
+	/// M-cycles elapsed since power on, other hardware blocks are synchronised on it
+	pub cycles: u64,
+	/// Prints every instruction as it runs
+	pub debug: bool,
 }
 
 impl CPU {
-	pub fn run(&mut self) {
-		loop {
-			let old_pc = self.registers.pc;
-			let (instruction, name) = Instruction::fetch(&mut self.memory, &mut self.registers.pc);
+	/// Runs until at least `cycles` M-cycles have elapsed
+	pub fn run_for(&mut self, cycles: u64) {
+		let target = self.cycles + cycles;
+		while self.cycles < target {
+			self.step();
+		}
+	}
+
+	/// Starts at 0x0100 with the registers and I/O state the boot rom leaves behind
+	pub fn skip_boot(&mut self) {
+		self.registers.write_reg16(Reg16::AF, 0x01B0);
+		self.registers.write_reg16(Reg16::BC, 0x0013);
+		self.registers.write_reg16(Reg16::DE, 0x00D8);
+		self.registers.write_reg16(Reg16::HL, 0x014D);
+		self.registers.sp = 0xFFFE;
+		self.registers.pc = 0x0100;
+		self.memory.skip_boot();
+	}
+
+	/// Services an interrupt or fetches and executes a single instruction,
+	/// then lets the rest of the hardware catch up. Returns the M-cycles it took
+	pub fn step(&mut self) -> u32 {
+		let cycles = match self.handle_interrupts() {
+			Some(cycles) => cycles,
+			None if self.halted => 1,
+			None => self.execute_next(),
+		};
+
+		self.cycles += cycles as u64;
+		self.memory.tick(cycles);
+		cycles
+	}
 
-			match instruction {
-				Instruction::Invalid => warn_or_crash(String::from("Invalid instruction")),
-				Instruction::Unknown => warn_or_crash(String::from("Unknown instruction? That's not supposed to happen")),
-				_ => println!("{} [{:#06x}]", name, old_pc),
-			}
+	fn execute_next(&mut self) -> u32 {
 
-			let (new_pc, did_overflow) = self.execute(&instruction);
+		let enable_ime = self.ime_scheduled;
+		let old_pc = self.registers.pc;
+		let (instruction, name) = Instruction::fetch(&self.memory, &mut self.registers.pc, self.halt_bug);
+		self.halt_bug = false;
 
-			if did_overflow {
-				warn_or_crash("Program counter overflowed".to_owned());
-			}
-			self.registers.pc = new_pc;
+		match instruction {
+			Instruction::Invalid => warn_or_crash(String::from("Invalid instruction")),
+			Instruction::Unknown => warn_or_crash(String::from("Unknown instruction? That's not supposed to happen")),
+			_ if self.debug => println!("{} [{:#06x}]", name, old_pc),
+			_ => {},
 		}
+
+		let branch_taken = match instruction.condition() {
+			Some(condition) => self.registers.f.is_condition_met(&condition),
+			None => false,
+		};
+		let (new_pc, did_overflow) = self.execute(&instruction);
+
+		if did_overflow {
+			warn_or_crash("Program counter overflowed".to_owned());
+		}
+		self.registers.pc = new_pc;
+
+		if enable_ime && self.ime_scheduled {
+			self.ime = true;
+			self.ime_scheduled = false;
+		}
+
+		instruction.m_cycles(branch_taken)
 	}
 
+	// A pending interrupt always ends HALT, but is only serviced when IME is set
+	fn handle_interrupts(&mut self) -> Option<u32> {
+		let interrupt = self.memory.interrupts.highest_pending()?;
+		let was_halted = self.halted;
+		self.halted = false;
+
+		if !self.ime {
+			return None
+		}
+
+		self.ime = false;
+		self.ime_scheduled = false;
+		self.memory.interrupts.acknowledge(interrupt);
+		// With EI right before the buggy HALT, the interrupt comes first and
+		// returns to the HALT, which then halts for real
+		if self.halt_bug {
+			self.halt_bug = false;
+			self.registers.pc = self.registers.pc.wrapping_sub(1);
+		}
+		self.push(self.registers.pc);
+		self.registers.pc = interrupt.vector();
+
+		Some(if was_halted { 6 } else { 5 })
+	}
+
+	// `fetch` leaves the PC on the last byte of the instruction it decoded,
+	// so the next instruction always starts at PC + 1
 	pub fn execute(&mut self, instruction: &Instruction) -> (u16, bool) {
-		self.registers.pc.overflowing_add(1)
+		let next_pc = self.registers.pc.overflowing_add(1);
+
+		match *instruction {
+			Instruction::NOP => {},
+			Instruction::LDmnnSP(nn) => self.write_2bytes(nn, self.registers.sp),
+			Instruction::LDrrNN(reg, nn) => self.registers.write_reg16(reg, nn),
+			Instruction::ADDhlRR(reg) => {
+				let hl = self.registers.get_reg16(Reg16::HL);
+				let value = self.registers.get_reg16(reg);
+				let (result, flags) = alu::add_hl(hl, value, self.registers.f);
+				self.registers.f = flags;
+				self.registers.write_reg16(Reg16::HL, result);
+			},
+			Instruction::LDmrrA(reg) => {
+				let address = self.registers.get_reg16(reg);
+				self.write_byte(address, self.registers.a);
+			},
+			Instruction::LDaMRR(reg) => {
+				let address = self.registers.get_reg16(reg);
+				self.registers.a = self.read_byte(address);
+			},
+			Instruction::INCrr(reg) => {
+				let value = self.registers.get_reg16(reg).wrapping_add(1);
+				self.registers.write_reg16(reg, value);
+			},
+			Instruction::DECrr(reg) => {
+				let value = self.registers.get_reg16(reg).wrapping_sub(1);
+				self.registers.write_reg16(reg, value);
+			},
+			Instruction::INCr(reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				let result = value.wrapping_add(1);
+				self.registers.f.z = result == 0;
+				self.registers.f.n = false;
+				self.registers.f.h = (value & 0x0F) == 0x0F;
+				self.registers.write_reg8(reg, result, &mut self.memory);
+			},
+			Instruction::DECr(reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				let result = value.wrapping_sub(1);
+				self.registers.f.z = result == 0;
+				self.registers.f.n = true;
+				self.registers.f.h = (value & 0x0F) == 0;
+				self.registers.write_reg8(reg, result, &mut self.memory);
+			},
+			Instruction::LDrN(reg, n) => self.registers.write_reg8(reg, n, &mut self.memory),
+			Instruction::RdCA(direction) => {
+				let (result, carry) = self.rotate(direction, self.registers.a, false);
+				self.registers.a = result;
+				self.registers.f = RegFlags { z: false, n: false, h: false, c: carry };
+			},
+			Instruction::RdA(direction) => {
+				let (result, carry) = self.rotate(direction, self.registers.a, true);
+				self.registers.a = result;
+				self.registers.f = RegFlags { z: false, n: false, h: false, c: carry };
+			},
+			Instruction::STOP => {},
+			Instruction::JRe(e) => return (Self::relative_target(next_pc.0, e), false),
+			Instruction::JRcE(condition, e) => {
+				if self.registers.f.is_condition_met(&condition) {
+					return (Self::relative_target(next_pc.0, e), false)
+				}
+			},
+			Instruction::LDImhlA => {
+				let hl = self.registers.get_reg16(Reg16::HL);
+				self.write_byte(hl, self.registers.a);
+				self.registers.write_reg16(Reg16::HL, hl.wrapping_add(1));
+			},
+			Instruction::LDIaMHL => {
+				let hl = self.registers.get_reg16(Reg16::HL);
+				self.registers.a = self.read_byte(hl);
+				self.registers.write_reg16(Reg16::HL, hl.wrapping_add(1));
+			},
+			Instruction::LDDmhlA => {
+				let hl = self.registers.get_reg16(Reg16::HL);
+				self.write_byte(hl, self.registers.a);
+				self.registers.write_reg16(Reg16::HL, hl.wrapping_sub(1));
+			},
+			Instruction::LDDaMHL => {
+				let hl = self.registers.get_reg16(Reg16::HL);
+				self.registers.a = self.read_byte(hl);
+				self.registers.write_reg16(Reg16::HL, hl.wrapping_sub(1));
+			},
+			Instruction::DAA => {
+				let mut a = self.registers.a;
+				if !self.registers.f.n {
+					if self.registers.f.c || a > 0x99 {
+						a = a.wrapping_add(0x60);
+						self.registers.f.c = true;
+					}
+					if self.registers.f.h || (a & 0x0F) > 0x09 {
+						a = a.wrapping_add(0x06);
+					}
+				} else {
+					if self.registers.f.c {
+						a = a.wrapping_sub(0x60);
+					}
+					if self.registers.f.h {
+						a = a.wrapping_sub(0x06);
+					}
+				}
+				self.registers.f.z = a == 0;
+				self.registers.f.h = false;
+				self.registers.a = a;
+			},
+			Instruction::CPL => {
+				self.registers.a = !self.registers.a;
+				self.registers.f.n = true;
+				self.registers.f.h = true;
+			},
+			Instruction::SCF => {
+				self.registers.f.n = false;
+				self.registers.f.h = false;
+				self.registers.f.c = true;
+			},
+			Instruction::CCF => {
+				self.registers.f.n = false;
+				self.registers.f.h = false;
+				self.registers.f.c = !self.registers.f.c;
+			},
+			Instruction::LDrR(dst, src) => {
+				let value = self.registers.get_reg8(src, &self.memory);
+				self.registers.write_reg8(dst, value, &mut self.memory);
+			},
+			Instruction::HALT => {
+				if !self.ime && self.memory.interrupts.pending() != 0 {
+					self.halt_bug = true;
+				} else {
+					self.halted = true;
+				}
+			},
+			Instruction::ALUaR(operation, reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				let (result, flags) = alu::apply(operation, self.registers.a, value, self.registers.f.c);
+				self.registers.a = result;
+				self.registers.f = flags;
+			},
+			Instruction::ALUaN(operation, n) => {
+				let (result, flags) = alu::apply(operation, self.registers.a, n, self.registers.f.c);
+				self.registers.a = result;
+				self.registers.f = flags;
+			},
+			Instruction::POPrr(reg) => {
+				let value = self.pop();
+				self.registers.write_reg16(reg, value);
+			},
+			Instruction::PUSHrr(reg) => {
+				let value = self.registers.get_reg16(reg);
+				self.push(value);
+			},
+			Instruction::RSTn(n) => {
+				self.push(next_pc.0);
+				return (n as u16, false)
+			},
+			Instruction::RETc(condition) => {
+				if self.registers.f.is_condition_met(&condition) {
+					return (self.pop(), false)
+				}
+			},
+			Instruction::RET => return (self.pop(), false),
+			Instruction::RETI => {
+				self.ime = true;
+				self.ime_scheduled = false;
+				return (self.pop(), false)
+			},
+			Instruction::JPcNN(condition, nn) => {
+				if self.registers.f.is_condition_met(&condition) {
+					return (nn, false)
+				}
+			},
+			Instruction::JPnn(nn) => return (nn, false),
+			Instruction::CALLcNN(condition, nn) => {
+				if self.registers.f.is_condition_met(&condition) {
+					self.push(next_pc.0);
+					return (nn, false)
+				}
+			},
+			Instruction::CALLnn(nn) => {
+				self.push(next_pc.0);
+				return (nn, false)
+			},
+			Instruction::ADDspN(e) => {
+				let (result, flags) = alu::add_sp(self.registers.sp, e);
+				self.registers.sp = result;
+				self.registers.f = flags;
+			},
+			Instruction::LDhlSPpN(e) => {
+				let (result, flags) = alu::add_sp(self.registers.sp, e);
+				self.registers.write_reg16(Reg16::HL, result);
+				self.registers.f = flags;
+			},
+			Instruction::LDmzpPnA(n) => self.write_byte(0xFF00 | n as u16, self.registers.a),
+			Instruction::LDaMZPpN(n) => self.registers.a = self.read_byte(0xFF00 | n as u16),
+			Instruction::LDmcA => self.write_byte(0xFF00 | self.registers.c as u16, self.registers.a),
+			Instruction::LDaMC => self.registers.a = self.read_byte(0xFF00 | self.registers.c as u16),
+			Instruction::LDmnnA(nn) => self.write_byte(nn, self.registers.a),
+			Instruction::LDaMNN(nn) => self.registers.a = self.read_byte(nn),
+			Instruction::JPhl => return (self.registers.get_reg16(Reg16::HL), false),
+			Instruction::LDspHL => self.registers.sp = self.registers.get_reg16(Reg16::HL),
+			Instruction::DI => {
+				self.ime = false;
+				self.ime_scheduled = false;
+			},
+			Instruction::EI => {
+				if !self.ime {
+					self.ime_scheduled = true;
+				}
+			},
+			Instruction::RdCr(direction, reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				let (result, carry) = self.rotate(direction, value, false);
+				self.registers.f = RegFlags { z: result == 0, n: false, h: false, c: carry };
+				self.registers.write_reg8(reg, result, &mut self.memory);
+			},
+			Instruction::RdR(direction, reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				let (result, carry) = self.rotate(direction, value, true);
+				self.registers.f = RegFlags { z: result == 0, n: false, h: false, c: carry };
+				self.registers.write_reg8(reg, result, &mut self.memory);
+			},
+			Instruction::SLAr(reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				let result = value << 1;
+				self.registers.f = RegFlags { z: result == 0, n: false, h: false, c: value & 0x80 != 0 };
+				self.registers.write_reg8(reg, result, &mut self.memory);
+			},
+			Instruction::SRAr(reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				let result = (value >> 1) | (value & 0x80);
+				self.registers.f = RegFlags { z: result == 0, n: false, h: false, c: value & 0x01 != 0 };
+				self.registers.write_reg8(reg, result, &mut self.memory);
+			},
+			Instruction::SWAPr(reg) => {
+				let result = self.registers.get_reg8(reg, &self.memory).rotate_left(4);
+				self.registers.f = RegFlags { z: result == 0, n: false, h: false, c: false };
+				self.registers.write_reg8(reg, result, &mut self.memory);
+			},
+			Instruction::SRLr(reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				let result = value >> 1;
+				self.registers.f = RegFlags { z: result == 0, n: false, h: false, c: value & 0x01 != 0 };
+				self.registers.write_reg8(reg, result, &mut self.memory);
+			},
+			Instruction::BITnR(bit, reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				self.registers.f.z = value & (1 << bit) == 0;
+				self.registers.f.n = false;
+				self.registers.f.h = true;
+			},
+			Instruction::RESnR(bit, reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				self.registers.write_reg8(reg, value & !(1 << bit), &mut self.memory);
+			},
+			Instruction::SETnR(bit, reg) => {
+				let value = self.registers.get_reg8(reg, &self.memory);
+				self.registers.write_reg8(reg, value | (1 << bit), &mut self.memory);
+			},
+			Instruction::Unknown | Instruction::Invalid => {},
+		}
+
+		next_pc
 	}
-}
 
-impl Default for CPU {
-	fn default() -> Self {
-		CPU {
-			memory: Default::default(),
-			registers: Default::default(),
+	// Rotates one bit, either circularly or through the carry flag,
+	// and returns the result along with the bit that got shifted out
+	fn rotate(&self, direction: Direction, value: u8, through_carry: bool) -> (u8, bool) {
+		let old_carry = self.registers.f.c as u8;
+		match direction {
+			Direction::Left => {
+				let bit_in = if through_carry { old_carry } else { value >> 7 };
+				((value << 1) | bit_in, value & 0x80 != 0)
+			},
+			_ => {
+				let bit_in = if through_carry { old_carry } else { value & 0x01 };
+				((value >> 1) | (bit_in << 7), value & 0x01 != 0)
+			},
 		}
 	}
-}
\ No newline at end of file
+
+	fn relative_target(pc: u16, e: u8) -> u16 {
+		pc.wrapping_add(e as i8 as u16)
+	}
+
+	// High byte first, which decides what ends up in IE when SP wraps onto 0xFFFF
+	fn push(&mut self, value: u16) {
+		self.registers.sp = self.registers.sp.wrapping_sub(1);
+		self.write_byte(self.registers.sp, (value >> 8) as u8);
+		self.registers.sp = self.registers.sp.wrapping_sub(1);
+		self.write_byte(self.registers.sp, (value & 0xFF) as u8);
+	}
+
+	fn pop(&mut self) -> u16 {
+		let value = self.read_2bytes(self.registers.sp);
+		self.registers.sp = self.registers.sp.wrapping_add(2);
+		value
+	}
+
+	fn read_byte(&self, address: u16) -> u8 {
+		self.memory.get_byte(address as usize)
+	}
+
+	fn read_2bytes(&self, address: u16) -> u16 {
+		self.memory.get_2bytes(address as usize)
+	}
+
+	fn write_byte(&mut self, address: u16, data: u8) {
+		self.memory.write_byte(address as usize, data);
+	}
+
+	fn write_2bytes(&mut self, address: u16, data: u16) {
+		self.memory.write_2bytes(address as usize, data);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::cartridge::Cartridge;
+	use super::super::interrupts::Interrupt;
+	use super::super::rtc::RtcClock;
+
+	fn cpu_with_program(program: &[u8]) -> CPU {
+		let mut rom = vec![0; 0x8000];
+		rom[0x100..0x100 + program.len()].copy_from_slice(program);
+		cpu_with_rom(rom)
+	}
+
+	fn cpu_with_rom(rom: Vec<u8>) -> CPU {
+		let mut cpu: CPU = Default::default();
+		cpu.memory.load_cartridge(Cartridge::new(rom, RtcClock::Emulated));
+		cpu.registers.pc = 0x0100;
+		cpu
+	}
+
+	#[test]
+	fn halt_bug_rereads_opcode_as_operand() {
+		// HALT; LD A,0x42
+		let mut cpu = cpu_with_program(&[0x76, 0x3E, 0x42]);
+		cpu.memory.interrupts.write_enable(Interrupt::VBlank.bit());
+		cpu.memory.interrupts.request(Interrupt::VBlank);
+
+		cpu.step();
+		assert!(cpu.halt_bug);
+		cpu.step();
+		assert_eq!(cpu.registers.a, 0x3E);
+		// 0x42 is then run as an opcode
+		assert_eq!(cpu.registers.pc, 0x0102);
+	}
+
+	#[test]
+	fn halt_bug_runs_single_byte_opcode_twice() {
+		// HALT; INC A
+		let mut cpu = cpu_with_program(&[0x76, 0x3C]);
+		cpu.memory.interrupts.write_enable(Interrupt::VBlank.bit());
+		cpu.memory.interrupts.request(Interrupt::VBlank);
+
+		cpu.step();
+		cpu.step();
+		cpu.step();
+		assert_eq!(cpu.registers.a, 2);
+		assert_eq!(cpu.registers.pc, 0x0102);
+	}
+
+	#[test]
+	fn halt_bug_after_ei_returns_to_halt() {
+		// EI; HALT, with LD A,0x42 as the VBlank handler
+		let mut rom = vec![0; 0x8000];
+		rom[0x40..0x42].copy_from_slice(&[0x3E, 0x42]);
+		rom[0x100..0x102].copy_from_slice(&[0xFB, 0x76]);
+		let mut cpu = cpu_with_rom(rom);
+		cpu.registers.sp = 0xFFFE;
+		cpu.memory.interrupts.write_enable(Interrupt::VBlank.bit());
+		cpu.memory.interrupts.request(Interrupt::VBlank);
+
+		cpu.step();
+		cpu.step();
+		cpu.step();
+		assert!(!cpu.halt_bug);
+		assert_eq!(cpu.registers.pc, 0x0040);
+		assert_eq!(cpu.memory.get_2bytes(0xFFFC), 0x0101);
+		cpu.step();
+		assert_eq!(cpu.registers.a, 0x42);
+		assert_eq!(cpu.registers.pc, 0x0042);
+	}
+}
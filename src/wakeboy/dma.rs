@@ -0,0 +1,56 @@
+/// Bytes copied into OAM by one transfer, one per M-cycle
+pub const DMA_LENGTH: usize = 0xA0;
+
+/// OAM DMA, started by writing the source page to 0xFF46
+#[derive(Default)]
+pub struct Dma {
+	/// Last value written to 0xFF46
+	register: u8,
+	/// Source of the running transfer
+	source: usize,
+	/// Next byte to copy, None when no transfer is running
+	index: Option<usize>,
+	/// A write to 0xFF46 takes one M-cycle before the transfer starts
+	start_pending: bool,
+}
+
+impl Dma {
+	pub fn read(&self) -> u8 {
+		self.register
+	}
+
+	/// Starting a new transfer while one is running restarts it from the new page
+	pub fn write(&mut self, data: u8) {
+		self.register = data;
+		self.start_pending = true;
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.index.is_some()
+	}
+
+	/// Address the transfer reads from this M-cycle, which is also what the CPU
+	/// gets when it tries to read on the same bus
+	pub fn current_source(&self) -> Option<usize> {
+		self.index.map(|index| self.source + index)
+	}
+
+	/// Advances one M-cycle, returns the source address and the OAM offset of
+	/// the byte to copy during it
+	pub fn tick(&mut self) -> Option<(usize, usize)> {
+		let transfer = self.index.map(|index| (self.source + index, index));
+		self.index = match self.index {
+			Some(index) if index + 1 < DMA_LENGTH => Some(index + 1),
+			_ => None,
+		};
+
+		if self.start_pending {
+			self.start_pending = false;
+			// Pages 0xE0-0xFF only see work ram through its echo
+			let source = (self.register as usize) << 8;
+			self.source = if source >= 0xE000 { source - 0x2000 } else { source };
+			self.index = Some(0);
+		}
+		transfer
+	}
+}
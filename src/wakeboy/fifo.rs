@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use super::sprite::Sprite;
+
+/// Pixels in a tile row, what the fetcher pushes at once
+const ROW_PIXELS: usize = 8;
+
+/// A pixel of the sprite FIFO, with what's needed to mix it with the background
+#[derive(Copy, Clone, Default)]
+pub struct ObjPixel {
+	pub color: u8,
+	pub obp1: bool,
+	pub behind_bg: bool,
+}
+
+/// Every step but the push takes 2 dots
+#[derive(Copy, Clone, Debug, Default, std::cmp::PartialEq)]
+pub enum FetcherStep {
+	#[default]
+	Tile,
+	DataLow,
+	DataHigh,
+	/// Waits for the background FIFO to empty
+	Push,
+}
+
+/// State of the line being drawn by the FIFO renderer
+#[derive(Default)]
+pub struct PixelFifo {
+	/// Color indexes, before the palette
+	pub bg: VecDeque<u8>,
+	pub obj: VecDeque<ObjPixel>,
+	pub step: FetcherStep,
+	/// Dots spent in the current fetcher step
+	pub step_dots: u8,
+	/// Tile column the fetcher is on, counted from the left of the layer
+	pub fetch_x: u8,
+	pub tile: u8,
+	pub low: u8,
+	pub high: u8,
+	/// The first tile fetched on a line is thrown away
+	pub first_fetch: bool,
+	/// Screen column of the next pixel out
+	pub x: usize,
+	/// Pixels left to drop before anything reaches the screen, for the fine scroll
+	pub discard: u8,
+	pub in_window: bool,
+	/// Sprites of the line not fetched yet, leftmost first
+	pub sprites: VecDeque<Sprite>,
+	/// Dots spent fetching the first of `sprites`, the FIFOs are stopped meanwhile
+	pub sprite_dots: Option<u8>,
+	/// Cleared once the 160 pixels of the line are out
+	pub drawing: bool,
+}
+
+impl PixelFifo {
+	pub fn new(discard: u8, sprites: Vec<Sprite>) -> Self {
+		PixelFifo {
+			bg: VecDeque::with_capacity(ROW_PIXELS * 2),
+			obj: VecDeque::with_capacity(ROW_PIXELS),
+			step: FetcherStep::Tile,
+			step_dots: 0,
+			fetch_x: 0,
+			tile: 0,
+			low: 0,
+			high: 0,
+			first_fetch: true,
+			x: 0,
+			discard,
+			in_window: false,
+			sprites: sprites.into(),
+			sprite_dots: None,
+			drawing: true,
+		}
+	}
+
+	pub fn next_step(&mut self, step: FetcherStep) {
+		self.step = step;
+		self.step_dots = 0;
+	}
+
+	/// In the middle of a tile, a sprite fetch has to wait for it to be done.
+	/// A fetcher waiting to push doesn't, or it would wait on the sprite forever
+	pub fn is_fetching(&self) -> bool {
+		match self.step {
+			FetcherStep::Tile => self.step_dots > 0,
+			FetcherStep::DataLow | FetcherStep::DataHigh => true,
+			FetcherStep::Push => false,
+		}
+	}
+
+	/// Only happens once the background FIFO is empty
+	pub fn push_bg_row(&mut self) {
+		for bit in (0..ROW_PIXELS).rev() {
+			self.bg.push_back(((self.high >> bit) & 1) << 1 | ((self.low >> bit) & 1));
+		}
+		self.fetch_x = self.fetch_x.wrapping_add(1);
+	}
+
+	/// The window restarts the fetcher from its first tile
+	pub fn start_window(&mut self) {
+		self.bg.clear();
+		self.next_step(FetcherStep::Tile);
+		self.fetch_x = 0;
+		self.in_window = true;
+	}
+
+	/// Opaque pixels already in the FIFO belong to sprites further left, which
+	/// win on the DMG, so only transparent ones get replaced. `skip` drops the
+	/// columns of a sprite hanging off the left of the screen
+	pub fn merge_sprite_row(&mut self, sprite: &Sprite, low: u8, high: u8, skip: usize) {
+		while self.obj.len() < ROW_PIXELS {
+			self.obj.push_back(ObjPixel::default());
+		}
+		for x in skip..ROW_PIXELS {
+			let pixel = &mut self.obj[x - skip];
+			if pixel.color == 0 {
+				*pixel = ObjPixel {
+					color: sprite.row_color(low, high, x),
+					obp1: sprite.uses_obp1(),
+					behind_bg: sprite.behind_bg(),
+				};
+			}
+		}
+	}
+}
@@ -1,16 +1,17 @@
 use super::conditionals::*;
 
-const Z_BIT_POS: u8 = 0;
-const N_BIT_POS: u8 = 1;
-const H_BIT_POS: u8 = 2;
-const C_BIT_POS: u8 = 3;
+// The flags live in the upper nibble of F, the lower nibble always reads as 0
+const Z_BIT_POS: u8 = 7;
+const N_BIT_POS: u8 = 6;
+const H_BIT_POS: u8 = 5;
+const C_BIT_POS: u8 = 4;
 
-#[derive(Default)]
+#[derive(Default, Copy, Clone)]
 pub struct RegFlags {
-	z: bool,
-	n: bool,
-	h: bool,
-	c: bool,
+	pub z: bool,
+	pub n: bool,
+	pub h: bool,
+	pub c: bool,
 }
 
 impl RegFlags {
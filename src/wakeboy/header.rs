@@ -0,0 +1,155 @@
+use super::core::*;
+
+const TITLE_BEGIN:				usize = 0x0134;
+const MANUFACTURER_BEGIN:		usize = 0x013F;
+const CGB_FLAG:					usize = 0x0143;
+const NEW_LICENSEE_BEGIN:		usize = 0x0144;
+const SGB_FLAG:					usize = 0x0146;
+const CARTRIDGE_TYPE:			usize = 0x0147;
+const ROM_SIZE:					usize = 0x0148;
+const RAM_SIZE:					usize = 0x0149;
+const DESTINATION_CODE:			usize = 0x014A;
+const OLD_LICENSEE:				usize = 0x014B;
+const VERSION:					usize = 0x014C;
+const HEADER_CHECKSUM:			usize = 0x014D;
+const GLOBAL_CHECKSUM_BEGIN:	usize = 0x014E;
+const HEADER_END:				usize = 0x0150-1;
+
+/// An old licensee code of 0x33 means the new licensee code is used instead
+const USE_NEW_LICENSEE: u8 = 0x33;
+
+/// Cartridge header, found at 0x0100-0x014F of every rom
+pub struct CartridgeHeader {
+	pub title: String,
+	/// Only present on CGB era cartridges, empty otherwise
+	pub manufacturer_code: String,
+	pub cgb_flag: u8,
+	pub new_licensee_code: String,
+	pub sgb_flag: u8,
+	pub cartridge_type: u8,
+	pub rom_size: u8,
+	pub ram_size: u8,
+	pub destination_code: u8,
+	pub old_licensee_code: u8,
+	pub version: u8,
+	pub header_checksum: u8,
+	pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+	/// Returns None if the rom is too small to hold a header
+	pub fn parse(rom: &[u8]) -> Option<Self> {
+		if rom.len() <= HEADER_END {
+			return None
+		}
+
+		let cgb_flag = rom[CGB_FLAG];
+		// On CGB cartridges the end of the title area holds the manufacturer code
+		let is_cgb = cgb_flag & 0x80 != 0;
+		let title_end = if is_cgb { MANUFACTURER_BEGIN } else { CGB_FLAG + 1 };
+		let manufacturer_code = if is_cgb { read_string(&rom[MANUFACTURER_BEGIN..CGB_FLAG]) } else { String::new() };
+
+		Some(CartridgeHeader {
+			title: read_string(&rom[TITLE_BEGIN..title_end]),
+			manufacturer_code,
+			cgb_flag,
+			new_licensee_code: read_string(&rom[NEW_LICENSEE_BEGIN..SGB_FLAG]),
+			sgb_flag: rom[SGB_FLAG],
+			cartridge_type: rom[CARTRIDGE_TYPE],
+			rom_size: rom[ROM_SIZE],
+			ram_size: rom[RAM_SIZE],
+			destination_code: rom[DESTINATION_CODE],
+			old_licensee_code: rom[OLD_LICENSEE],
+			version: rom[VERSION],
+			header_checksum: rom[HEADER_CHECKSUM],
+			// Unlike everything else on the Gameboy, this one is big-endian
+			global_checksum: combine_bytes(rom[GLOBAL_CHECKSUM_BEGIN + 1], rom[GLOBAL_CHECKSUM_BEGIN]),
+		})
+	}
+
+	/// Checksum the boot rom verifies over 0x0134-0x014C
+	pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+		rom[TITLE_BEGIN..HEADER_CHECKSUM].iter()
+			.fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1))
+	}
+
+	/// Sum of every byte of the rom except the global checksum itself
+	pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+		rom.iter().enumerate()
+			.filter(|(i, _)| *i != GLOBAL_CHECKSUM_BEGIN && *i != GLOBAL_CHECKSUM_BEGIN + 1)
+			.fold(0u16, |x, (_, b)| x.wrapping_add(*b as u16))
+	}
+
+	pub fn licensee(&self) -> String {
+		if self.old_licensee_code == USE_NEW_LICENSEE {
+			self.new_licensee_code.clone()
+		} else {
+			format!("{:#04x}", self.old_licensee_code)
+		}
+	}
+
+	pub fn rom_size_bytes(&self) -> Option<usize> {
+		match self.rom_size {
+			0x00 ..= 0x08 => Some(0x8000 << self.rom_size),
+			_ => None,
+		}
+	}
+
+	pub fn ram_size_bytes(&self) -> Option<usize> {
+		match self.ram_size {
+			0x00 => Some(0),
+			0x01 => Some(0x800),
+			0x02 => Some(0x2000),
+			0x03 => Some(0x8000),
+			0x04 => Some(0x20000),
+			0x05 => Some(0x10000),
+			_ => None,
+		}
+	}
+
+	pub fn has_battery(&self) -> bool {
+		matches!(self.cartridge_type, 0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF)
+	}
+
+	pub fn cartridge_type_name(&self) -> &'static str {
+		match self.cartridge_type {
+			0x00 => "ROM ONLY",
+			0x01 => "MBC1",
+			0x02 => "MBC1+RAM",
+			0x03 => "MBC1+RAM+BATTERY",
+			0x05 => "MBC2",
+			0x06 => "MBC2+BATTERY",
+			0x08 => "ROM+RAM",
+			0x09 => "ROM+RAM+BATTERY",
+			0x0B => "MMM01",
+			0x0C => "MMM01+RAM",
+			0x0D => "MMM01+RAM+BATTERY",
+			0x0F => "MBC3+TIMER+BATTERY",
+			0x10 => "MBC3+TIMER+RAM+BATTERY",
+			0x11 => "MBC3",
+			0x12 => "MBC3+RAM",
+			0x13 => "MBC3+RAM+BATTERY",
+			0x19 => "MBC5",
+			0x1A => "MBC5+RAM",
+			0x1B => "MBC5+RAM+BATTERY",
+			0x1C => "MBC5+RUMBLE",
+			0x1D => "MBC5+RUMBLE+RAM",
+			0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+			0x20 => "MBC6",
+			0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+			0xFC => "POCKET CAMERA",
+			0xFD => "BANDAI TAMA5",
+			0xFE => "HuC3",
+			0xFF => "HuC1+RAM+BATTERY",
+			_ => "Unknown",
+		}
+	}
+}
+
+// Header strings are ASCII padded with 0's
+fn read_string(bytes: &[u8]) -> String {
+	bytes.iter()
+		.take_while(|b| **b != 0)
+		.map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '?' })
+		.collect()
+}
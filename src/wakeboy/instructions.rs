@@ -58,7 +58,8 @@ pub enum Instruction {
 	EI,
 	RdCr		(Direction, Reg8),
 	RdR 		(Direction, Reg8),
-	SdAr		(Direction, Reg8),
+	SLAr		(Reg8),
+	SRAr		(Reg8),
 	SWAPr		(Reg8),
 	SRLr		(Reg8),
 	BITnR		(u8, Reg8),
@@ -66,18 +67,80 @@ pub enum Instruction {
 	SETnR		(u8, Reg8),
 	Unknown,
 	Invalid,
-	Unassigned,
 }
 
 impl Instruction {
 
-	pub fn fetch(mem: &mut MemoryBus, pc: &mut u16) -> (Instruction, String) {
+	/// Returns the condition a conditional jump, call or return depends on
+	pub fn condition(&self) -> Option<Conditional> {
+		match *self {
+			Instruction::JRcE(c, _) | Instruction::JPcNN(c, _) |
+			Instruction::CALLcNN(c, _) | Instruction::RETc(c) => Some(c),
+			_ => None,
+		}
+	}
+
+	/// Machine cycles (4 clock ticks each) taken by the instruction
+	/// `branch_taken` only matters for conditional instructions
+	pub fn m_cycles(&self, branch_taken: bool) -> u32 {
+		let hl = |reg: Reg8, r: u32, mhl: u32| if reg == Reg8::MHL { mhl } else { r };
+		let branch = |taken: u32, not_taken: u32| if branch_taken { taken } else { not_taken };
+
+		match *self {
+			Instruction::NOP => 1,
+			Instruction::LDmnnSP(_) => 5,
+			Instruction::LDrrNN(..) => 3,
+			Instruction::ADDhlRR(_) => 2,
+			Instruction::LDmrrA(_) | Instruction::LDaMRR(_) => 2,
+			Instruction::INCrr(_) | Instruction::DECrr(_) => 2,
+			Instruction::INCr(reg) | Instruction::DECr(reg) => hl(reg, 1, 3),
+			Instruction::LDrN(reg, _) => hl(reg, 2, 3),
+			Instruction::RdCA(_) | Instruction::RdA(_) => 1,
+			Instruction::STOP => 1,
+			Instruction::JRe(_) => 3,
+			Instruction::JRcE(..) => branch(3, 2),
+			Instruction::LDImhlA | Instruction::LDIaMHL |
+			Instruction::LDDmhlA | Instruction::LDDaMHL => 2,
+			Instruction::DAA | Instruction::CPL | Instruction::SCF | Instruction::CCF => 1,
+			Instruction::LDrR(dst, src) => if dst == Reg8::MHL || src == Reg8::MHL { 2 } else { 1 },
+			Instruction::HALT => 1,
+			Instruction::ALUaR(_, reg) => hl(reg, 1, 2),
+			Instruction::ALUaN(..) => 2,
+			Instruction::POPrr(_) => 3,
+			Instruction::PUSHrr(_) => 4,
+			Instruction::RSTn(_) => 4,
+			Instruction::RETc(_) => branch(5, 2),
+			Instruction::RET | Instruction::RETI => 4,
+			Instruction::JPcNN(..) => branch(4, 3),
+			Instruction::JPnn(_) => 4,
+			Instruction::CALLcNN(..) => branch(6, 3),
+			Instruction::CALLnn(_) => 6,
+			Instruction::ADDspN(_) => 4,
+			Instruction::LDhlSPpN(_) => 3,
+			Instruction::LDmzpPnA(_) | Instruction::LDaMZPpN(_) => 3,
+			Instruction::LDmcA | Instruction::LDaMC => 2,
+			Instruction::LDmnnA(_) | Instruction::LDaMNN(_) => 4,
+			Instruction::JPhl => 1,
+			Instruction::LDspHL => 2,
+			Instruction::DI | Instruction::EI => 1,
+			Instruction::RdCr(_, reg) | Instruction::RdR(_, reg) |
+			Instruction::SLAr(reg) | Instruction::SRAr(reg) |
+			Instruction::SWAPr(reg) | Instruction::SRLr(reg) |
+			Instruction::RESnR(_, reg) | Instruction::SETnR(_, reg) => hl(reg, 2, 4),
+			Instruction::BITnR(_, reg) => hl(reg, 2, 3),
+			Instruction::Unknown | Instruction::Invalid => 1,
+		}
+	}
+
+	/// After the HALT bug the PC didn't move past the opcode: operands start on
+	/// the opcode byte, and an opcode without any runs twice
+	pub fn fetch(mem: &MemoryBus, pc: &mut u16, halt_bug: bool) -> (Instruction, String) {
+
+		let opcode = mem.get_byte(*pc as usize);
+		if halt_bug {
+			*pc = pc.wrapping_sub(1);
+		}
 
-		let opcode = match mem.get_byte(*pc as usize) {
-			None => return (Instruction::Unassigned, "Unassigned".to_owned()),
-			Some(o) => o,
-		};
-		
 		if opcode == 0x00 {
 			return (Instruction::NOP, "NOP".to_owned())
 		}
@@ -99,14 +162,15 @@ impl Instruction {
 							0b01 => {
 								let direction = Direction::from((opcode & 0b00001000) >> 3);
 								let reg = Reg8::from(opcode & 0b111);
-								return (Instruction::RdR(Direction::from((header & 0b00001000) >> 3), Reg8::from(opcode & 0b111)),
+								return (Instruction::RdR(direction, reg),
 								format!("RdR {}, {}", direction, reg))
 							},
 							0b10 => {
-								let direction = Direction::from((opcode & 0b00001000) >> 3);
 								let reg = Reg8::from(opcode & 0b111);
-								return (Instruction::SdAr(Direction::from((opcode & 0b00001000) >> 3), Reg8::from(opcode & 0b111)),
-								format!("SdAr {}, {}", direction, reg))
+								if (opcode & 0b1000) >> 3 == 0 {
+									return (Instruction::SLAr(reg), format!("SLA {}", reg))
+								}
+								return (Instruction::SRAr(reg), format!("SRA {}", reg))
 							},
 							0b11 => {
 								let reg = Reg8::from(opcode & 0b111);
@@ -142,7 +206,11 @@ impl Instruction {
 				let n = mem.get_imm16(pc);
 				return (Instruction::LDmnnSP(n), format!("LD ({:#06x}), SP", n))
 			},
-			0x10 => return (Instruction::STOP, "STOP".to_owned()),
+			0x10 => {
+				// STOP is followed by a padding byte that gets skipped
+				mem.get_imm8(pc);
+				return (Instruction::STOP, "STOP".to_owned())
+			},
 			0x18 => {
 				let n = mem.get_imm8(pc);
 				return (Instruction::JRe(n), format!("JR pc({:#06x})+{:#04x}", *pc, n))
@@ -223,11 +291,11 @@ impl Instruction {
 						return (Instruction::LDaMRR(reg), format!("LD A {}", reg))
 					},
 					0b0011 => {
-						let reg = Reg16::from(six_bit_rem >> 4, false);
+						let reg = Reg16::from(six_bit_rem >> 4, true);
 						return (Instruction::INCrr(reg), format!("INC {}", reg))
 					},
 					0b1011 => {
-						let reg = Reg16::from(six_bit_rem >> 4, false);
+						let reg = Reg16::from(six_bit_rem >> 4, true);
 						return (Instruction::DECrr(reg), format!("DEC {}", reg))
 					},
 					_ => {}
@@ -251,9 +319,9 @@ impl Instruction {
 					0b111 => {
 						match six_bit_rem >> 4 {
 							0 => return (Instruction::RdCA(Direction::from((six_bit_rem & 0b1000) >> 3)),
-										 format!("RdCA")),
+										 "RdCA".to_owned()),
 							1 => return (Instruction::RdA(Direction::from((six_bit_rem & 0b1000) >> 3)),
-										format!("RdA")),
+										"RdA".to_owned()),
 							_ => return (Instruction::Invalid, "Invalid".to_owned())
 						}
 					},
@@ -291,7 +359,7 @@ impl Instruction {
 						let reg = Reg16::from(six_bit_rem >> 4, false);
 						return (Instruction::POPrr(reg), format!("POP {}", reg))
 					},
-					0b101 => {
+					0b101 if six_bit_rem & 0b1000 == 0 => {
 						let reg = Reg16::from(six_bit_rem >> 4, false);
 						return (Instruction::PUSHrr(reg), format!("PUSH {}", reg))
 					}
@@ -308,7 +376,7 @@ impl Instruction {
 						let n = mem.get_imm16(pc);
 						return (Instruction::JPcNN(conditional, n), format!("JP {}, {:#06x}", conditional, n))
 					},
-					0b100 => {
+					0b100 if six_bit_rem & 0b100000 == 0 => {
 						let conditional = Conditional::from((six_bit_rem & 0b011000) >> 3);
 						let n = mem.get_imm16(pc);
 						return (Instruction::CALLcNN(conditional, n), format!("CALL {}, {:#06x}", conditional, n))
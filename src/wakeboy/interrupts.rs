@@ -0,0 +1,98 @@
+#[derive(Copy, Clone, std::cmp::PartialEq)]
+pub enum Interrupt {
+	VBlank,
+	Stat,
+	Timer,
+	Serial,
+	Joypad,
+}
+
+// Ordered by priority, the lowest bit is serviced first
+const INTERRUPTS: [Interrupt; 5] = [
+	Interrupt::VBlank,
+	Interrupt::Stat,
+	Interrupt::Timer,
+	Interrupt::Serial,
+	Interrupt::Joypad,
+];
+
+const INTERRUPT_MASK: u8 = 0b0001_1111;
+
+impl Interrupt {
+	pub fn bit(&self) -> u8 {
+		match *self {
+			Interrupt::VBlank => 0b0000_0001,
+			Interrupt::Stat => 0b0000_0010,
+			Interrupt::Timer => 0b0000_0100,
+			Interrupt::Serial => 0b0000_1000,
+			Interrupt::Joypad => 0b0001_0000,
+		}
+	}
+
+	pub fn vector(&self) -> u16 {
+		match *self {
+			Interrupt::VBlank => 0x0040,
+			Interrupt::Stat => 0x0048,
+			Interrupt::Timer => 0x0050,
+			Interrupt::Serial => 0x0058,
+			Interrupt::Joypad => 0x0060,
+		}
+	}
+}
+
+impl std::fmt::Display for Interrupt {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let ret = match *self {
+			Interrupt::VBlank => "VBlank",
+			Interrupt::Stat => "STAT",
+			Interrupt::Timer => "Timer",
+			Interrupt::Serial => "Serial",
+			Interrupt::Joypad => "Joypad",
+		};
+		write!(f, "{}", ret)
+	}
+}
+
+/// IE (0xFFFF) and IF (0xFF0F), IME is owned by the CPU
+#[derive(Default)]
+pub struct InterruptController {
+	enable: u8,
+	flag: u8,
+}
+
+impl InterruptController {
+	pub fn request(&mut self, interrupt: Interrupt) {
+		self.flag |= interrupt.bit();
+	}
+
+	pub fn acknowledge(&mut self, interrupt: Interrupt) {
+		self.flag &= !interrupt.bit();
+	}
+
+	/// Interrupts that are both requested and enabled, regardless of IME
+	pub fn pending(&self) -> u8 {
+		self.enable & self.flag & INTERRUPT_MASK
+	}
+
+	pub fn highest_pending(&self) -> Option<Interrupt> {
+		let pending = self.pending();
+		INTERRUPTS.iter().copied().find(|i| pending & i.bit() != 0)
+	}
+
+	pub fn read_enable(&self) -> u8 {
+		self.enable
+	}
+
+	pub fn write_enable(&mut self, data: u8) {
+		self.enable = data;
+	}
+
+	/// The unused upper 3 bits are set by the bus, like for every I/O register
+	pub fn read_flag(&self) -> u8 {
+		self.flag
+	}
+
+	pub fn write_flag(&mut self, data: u8) {
+		self.flag = data & INTERRUPT_MASK;
+	}
+}
@@ -0,0 +1,119 @@
+use super::timer;
+
+pub const P1:			usize = 0xFF00;
+pub const SB:			usize = 0xFF01;
+pub const SC:			usize = 0xFF02;
+pub const IF:			usize = 0xFF0F;
+pub const NR10:			usize = 0xFF10;
+pub const NR11:			usize = 0xFF11;
+pub const NR12:			usize = 0xFF12;
+pub const NR13:			usize = 0xFF13;
+pub const NR14:			usize = 0xFF14;
+pub const NR21:			usize = 0xFF16;
+pub const NR22:			usize = 0xFF17;
+pub const NR23:			usize = 0xFF18;
+pub const NR24:			usize = 0xFF19;
+pub const NR30:			usize = 0xFF1A;
+pub const NR31:			usize = 0xFF1B;
+pub const NR32:			usize = 0xFF1C;
+pub const NR33:			usize = 0xFF1D;
+pub const NR34:			usize = 0xFF1E;
+pub const NR41:			usize = 0xFF20;
+pub const NR42:			usize = 0xFF21;
+pub const NR43:			usize = 0xFF22;
+pub const NR44:			usize = 0xFF23;
+pub const NR50:			usize = 0xFF24;
+pub const NR51:			usize = 0xFF25;
+pub const NR52:			usize = 0xFF26;
+pub const WAVE_RAM_BEGIN:	usize = 0xFF30;
+pub const WAVE_RAM_END:		usize = 0xFF40-1;
+pub const LCDC:			usize = 0xFF40;
+pub const STAT:			usize = 0xFF41;
+pub const SCY:			usize = 0xFF42;
+pub const SCX:			usize = 0xFF43;
+pub const LY:			usize = 0xFF44;
+pub const LYC:			usize = 0xFF45;
+pub const DMA:			usize = 0xFF46;
+pub const BGP:			usize = 0xFF47;
+pub const OBP0:			usize = 0xFF48;
+pub const OBP1:			usize = 0xFF49;
+pub const WY:			usize = 0xFF4A;
+pub const WX:			usize = 0xFF4B;
+pub const BOOT_ROM_DISABLE:	usize = 0xFF50;
+
+/// Hardware block an I/O register is wired to
+#[derive(Copy, Clone, Debug, std::cmp::PartialEq)]
+pub enum Peripheral {
+	Joypad,
+	Serial,
+	Timer,
+	Interrupts,
+	Sound,
+	Lcd,
+	Dma,
+	BootRom,
+	/// Nothing answers, reads are open bus and writes are lost
+	Unmapped,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct IoRegister {
+	pub peripheral: Peripheral,
+	/// Bits that read back, the others always read as 1
+	pub read_mask: u8,
+	/// Bits the CPU can change, the others keep their value
+	pub write_mask: u8,
+}
+
+impl IoRegister {
+	const fn new(peripheral: Peripheral, read_mask: u8, write_mask: u8) -> Self {
+		IoRegister { peripheral, read_mask, write_mask }
+	}
+
+	/// Applies the read mask to what the peripheral holds
+	pub fn read(&self, value: u8) -> u8 {
+		value | !self.read_mask
+	}
+
+	/// Merges the writable bits of `data` into the current value
+	pub fn merge(&self, current: u8, data: u8) -> u8 {
+		(current & !self.write_mask) | (data & self.write_mask)
+	}
+}
+
+/// Register layout of the DMG, unused bits from the Pan Docs
+pub fn register(address: usize) -> IoRegister {
+	use Peripheral::*;
+	match address {
+		// The low nibble is the button state, the upper bits select the button row
+		P1					=> IoRegister::new(Joypad, 0x3F, 0x30),
+		SB					=> IoRegister::new(Serial, 0xFF, 0xFF),
+		SC					=> IoRegister::new(Serial, 0x81, 0x81),
+		timer::DIV ..= timer::TMA	=> IoRegister::new(Timer, 0xFF, 0xFF),
+		timer::TAC			=> IoRegister::new(Timer, 0x07, 0x07),
+		IF					=> IoRegister::new(Interrupts, 0x1F, 0x1F),
+		NR10				=> IoRegister::new(Sound, 0x7F, 0x7F),
+		NR11 | NR21			=> IoRegister::new(Sound, 0xC0, 0xFF),
+		NR12 | NR22 | NR42	=> IoRegister::new(Sound, 0xFF, 0xFF),
+		// Frequency registers are write-only
+		NR13 | NR23 | NR33	=> IoRegister::new(Sound, 0x00, 0xFF),
+		NR14 | NR24 | NR34 | NR44	=> IoRegister::new(Sound, 0x40, 0xC7),
+		NR30				=> IoRegister::new(Sound, 0x80, 0x80),
+		NR31 | NR41			=> IoRegister::new(Sound, 0x00, 0xFF),
+		NR32				=> IoRegister::new(Sound, 0x60, 0x60),
+		NR43 | NR50 | NR51	=> IoRegister::new(Sound, 0xFF, 0xFF),
+		// Bits 0-3 report which channels are playing and can't be written
+		NR52				=> IoRegister::new(Sound, 0x8F, 0x80),
+		WAVE_RAM_BEGIN ..= WAVE_RAM_END	=> IoRegister::new(Sound, 0xFF, 0xFF),
+		LCDC				=> IoRegister::new(Lcd, 0xFF, 0xFF),
+		// The mode and coincidence bits are set by the LCD itself
+		STAT				=> IoRegister::new(Lcd, 0x7F, 0x78),
+		SCY | SCX | LYC		=> IoRegister::new(Lcd, 0xFF, 0xFF),
+		LY					=> IoRegister::new(Lcd, 0xFF, 0x00),
+		BGP | OBP0 | OBP1	=> IoRegister::new(Lcd, 0xFF, 0xFF),
+		WY | WX				=> IoRegister::new(Lcd, 0xFF, 0xFF),
+		DMA					=> IoRegister::new(Dma, 0xFF, 0xFF),
+		BOOT_ROM_DISABLE	=> IoRegister::new(BootRom, 0x00, 0xFF),
+		_					=> IoRegister::new(Unmapped, 0x00, 0x00),
+	}
+}
@@ -0,0 +1,95 @@
+use super::core::*;
+use super::mbc1::*;
+use super::mbc2::*;
+use super::mbc3::*;
+use super::mbc5::*;
+use super::rtc::*;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+/// Called with the new motor state whenever a rumble cartridge turns it on or off
+pub type RumbleHandler = Box<dyn FnMut(bool)>;
+
+/// Memory bank controller of a cartridge
+/// ROM addresses are absolute (0x0000-0x7FFF), RAM addresses are relative to 0xA000
+pub trait BankController {
+	fn read_rom(&self, rom: &[u8], address: usize) -> u8;
+	/// Writes to the ROM area go to the controller's registers
+	fn write_rom(&mut self, address: usize, data: u8);
+	fn read_ram(&self, ram: &[u8], address: usize) -> u8;
+	/// Returns whether the data was stored in the ram, rather than lost or
+	/// sent to another register
+	fn write_ram(&mut self, ram: &mut [u8], address: usize, data: u8) -> bool;
+	/// Called with the M-cycles elapsed, for controllers with their own clock
+	fn tick(&mut self, _cycles: u32) {}
+	/// Ram that comes with the controller, regardless of the header's ram size
+	fn built_in_ram_size(&self) -> Option<usize> {
+		None
+	}
+	/// Only rumble cartridges ever call the handler
+	fn set_rumble_handler(&mut self, _handler: RumbleHandler) {}
+	fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+		None
+	}
+}
+
+/// Picks the controller from the cartridge type byte of the header
+pub fn from_cartridge_type(cartridge_type: u8, rom: &[u8], rtc_clock: RtcClock) -> Box<dyn BankController> {
+	match cartridge_type {
+		0x00 | 0x08 | 0x09 => Box::new(NoMbc),
+		0x01 ..= 0x03 => Box::new(Mbc1::new(rom)),
+		0x05 | 0x06 => Box::new(Mbc2::default()),
+		0x0F | 0x10 => Box::new(Mbc3::new(Some(Rtc::new(rtc_clock)))),
+		0x11 ..= 0x13 => Box::new(Mbc3::new(None)),
+		0x19 ..= 0x1B => Box::new(Mbc5::new(false)),
+		0x1C ..= 0x1E => Box::new(Mbc5::new(true)),
+		_ => {
+			warn_or_crash(format!("Unsupported cartridge type ({:#04x}), it will run as a plain 32 KiB rom", cartridge_type));
+			Box::new(NoMbc)
+		}
+	}
+}
+
+/// Reads from a given 16 KiB bank, banks past the end of the rom wrap around
+/// (rom sizes are always powers of two)
+pub fn rom_bank_byte(rom: &[u8], bank: usize, address: usize) -> u8 {
+	rom[(bank * ROM_BANK_SIZE + (address & (ROM_BANK_SIZE - 1))) & (rom.len() - 1)]
+}
+
+/// Same as `rom_bank_byte` for 8 KiB ram banks, 0xFF when there's no ram
+pub fn ram_bank_byte(ram: &[u8], bank: usize, address: usize) -> u8 {
+	if ram.is_empty() {
+		return 0xFF
+	}
+	ram[(bank * RAM_BANK_SIZE + address) & (ram.len() - 1)]
+}
+
+/// False when there's no ram to write to
+pub fn write_ram_bank(ram: &mut [u8], bank: usize, address: usize, data: u8) -> bool {
+	if ram.is_empty() {
+		return false
+	}
+	let mask = ram.len() - 1;
+	ram[(bank * RAM_BANK_SIZE + address) & mask] = data;
+	true
+}
+
+/// 32 KiB of rom and optionally up to 8 KiB of ram, without any banking
+pub struct NoMbc;
+
+impl BankController for NoMbc {
+	fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+		rom[address & (rom.len() - 1)]
+	}
+
+	fn write_rom(&mut self, _address: usize, _data: u8) {}
+
+	fn read_ram(&self, ram: &[u8], address: usize) -> u8 {
+		ram_bank_byte(ram, 0, address)
+	}
+
+	fn write_ram(&mut self, ram: &mut [u8], address: usize, data: u8) -> bool {
+		write_ram_bank(ram, 0, address, data)
+	}
+}
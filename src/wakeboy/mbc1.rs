@@ -0,0 +1,170 @@
+use super::mbc::*;
+
+const LOGO_BEGIN: usize = 0x0104;
+const LOGO_END: usize = 0x0134;
+/// Multicarts hold a separate game (with its own header) every 16 banks
+const MULTICART_GAME_BANKS: usize = 0x10;
+
+pub struct Mbc1 {
+	ram_enabled: bool,
+	/// BANK1, lower bits of the rom bank number
+	rom_bank: u8,
+	/// BANK2, either the ram bank or the upper bits of the rom bank number
+	upper_bank: u8,
+	/// 0: BANK2 only applies to 0x4000-0x7FFF, 1: it applies to every area
+	advanced_banking: bool,
+	/// MBC1M wires BANK2 one bit lower, leaving BANK1 with only 4 bits
+	multicart: bool,
+}
+
+impl Mbc1 {
+	pub fn new(rom: &[u8]) -> Self {
+		Mbc1 {
+			ram_enabled: false,
+			rom_bank: 1,
+			upper_bank: 0,
+			advanced_banking: false,
+			multicart: Self::is_multicart(rom),
+		}
+	}
+
+	// There's no header flag for multicarts, but they are all 1 MiB and
+	// have a second Nintendo logo in the header of their second game
+	fn is_multicart(rom: &[u8]) -> bool {
+		let second_header = MULTICART_GAME_BANKS * ROM_BANK_SIZE;
+		rom.len() == 0x10_0000 &&
+			rom[LOGO_BEGIN..LOGO_END] == rom[second_header + LOGO_BEGIN..second_header + LOGO_END]
+	}
+
+	fn upper_bank_shift(&self) -> usize {
+		if self.multicart { 4 } else { 5 }
+	}
+
+	fn lower_bank(&self) -> usize {
+		let bank = self.rom_bank as usize;
+		if self.multicart { bank & 0x0F } else { bank }
+	}
+}
+
+impl BankController for Mbc1 {
+	fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+		let upper = (self.upper_bank as usize) << self.upper_bank_shift();
+		match address {
+			0x0000 ..= 0x3FFF => {
+				let bank = if self.advanced_banking { upper } else { 0 };
+				rom_bank_byte(rom, bank, address)
+			},
+			_ => rom_bank_byte(rom, upper | self.lower_bank(), address),
+		}
+	}
+
+	fn write_rom(&mut self, address: usize, data: u8) {
+		match address {
+			0x0000 ..= 0x1FFF => self.ram_enabled = data & 0x0F == 0x0A,
+			0x2000 ..= 0x3FFF => {
+				// Bank 0 can't be selected here, it gets bumped to 1 before the
+				// multicart wiring drops the 5th bit, so 0x10 still maps to 0x00
+				let bank = data & 0b1_1111;
+				self.rom_bank = if bank == 0 { 1 } else { bank };
+			},
+			0x4000 ..= 0x5FFF => self.upper_bank = data & 0b11,
+			_ => self.advanced_banking = data & 0b1 != 0,
+		}
+	}
+
+	fn read_ram(&self, ram: &[u8], address: usize) -> u8 {
+		if !self.ram_enabled {
+			return 0xFF
+		}
+		let bank = if self.advanced_banking { self.upper_bank as usize } else { 0 };
+		ram_bank_byte(ram, bank, address)
+	}
+
+	fn write_ram(&mut self, ram: &mut [u8], address: usize, data: u8) -> bool {
+		if !self.ram_enabled {
+			return false
+		}
+		let bank = if self.advanced_banking { self.upper_bank as usize } else { 0 };
+		write_ram_bank(ram, bank, address, data)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Every bank starts with its own number
+	fn numbered_rom(banks: usize) -> Vec<u8> {
+		let mut rom = vec![0; banks * ROM_BANK_SIZE];
+		for bank in 0..banks {
+			rom[bank * ROM_BANK_SIZE] = bank as u8;
+		}
+		rom
+	}
+
+	fn multicart_rom() -> Vec<u8> {
+		let mut rom = numbered_rom(64);
+		let second_header = MULTICART_GAME_BANKS * ROM_BANK_SIZE;
+		for i in LOGO_BEGIN..LOGO_END {
+			rom[i] = i as u8;
+			rom[second_header + i] = i as u8;
+		}
+		rom
+	}
+
+	#[test]
+	fn zero_bank_selects_the_next_one() {
+		let rom = numbered_rom(128);
+		let mut mbc = Mbc1::new(&rom);
+		for upper in 0..4 {
+			mbc.write_rom(0x4000, upper);
+			mbc.write_rom(0x2000, 0x00);
+			assert_eq!(mbc.read_rom(&rom, 0x4000), upper * 0x20 + 1);
+		}
+	}
+
+	#[test]
+	fn upper_bits_only_reach_the_first_area_in_mode_1() {
+		let rom = numbered_rom(128);
+		let mut mbc = Mbc1::new(&rom);
+		mbc.write_rom(0x4000, 2);
+		mbc.write_rom(0x2000, 3);
+		assert_eq!(mbc.read_rom(&rom, 0x0000), 0x00);
+		assert_eq!(mbc.read_rom(&rom, 0x4000), 0x43);
+
+		mbc.write_rom(0x6000, 1);
+		assert_eq!(mbc.read_rom(&rom, 0x0000), 0x40);
+		assert_eq!(mbc.read_rom(&rom, 0x4000), 0x43);
+	}
+
+	#[test]
+	fn multicart_shifts_the_upper_bits_by_4() {
+		let rom = multicart_rom();
+		let mut mbc = Mbc1::new(&rom);
+		assert!(mbc.multicart);
+		mbc.write_rom(0x4000, 1);
+		mbc.write_rom(0x2000, 0x02);
+		assert_eq!(mbc.read_rom(&rom, 0x4000), 0x12);
+		// The 5th bit of BANK1 isn't wired
+		mbc.write_rom(0x2000, 0x12);
+		assert_eq!(mbc.read_rom(&rom, 0x4000), 0x12);
+		// Not 0 so it isn't bumped, then loses its only set bit
+		mbc.write_rom(0x2000, 0x10);
+		assert_eq!(mbc.read_rom(&rom, 0x4000), 0x10);
+
+		mbc.write_rom(0x6000, 1);
+		mbc.write_rom(0x4000, 2);
+		assert_eq!(mbc.read_rom(&rom, 0x0000), 0x20);
+	}
+
+	#[test]
+	fn plain_1mib_rom_isnt_a_multicart() {
+		let mut rom = multicart_rom();
+		rom[MULTICART_GAME_BANKS * ROM_BANK_SIZE + LOGO_BEGIN] ^= 0xFF;
+		let mut mbc = Mbc1::new(&rom);
+		assert!(!mbc.multicart);
+		mbc.write_rom(0x4000, 1);
+		mbc.write_rom(0x2000, 0x02);
+		assert_eq!(mbc.read_rom(&rom, 0x4000), 0x22);
+	}
+}
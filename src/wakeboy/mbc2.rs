@@ -0,0 +1,64 @@
+use super::mbc::*;
+
+/// 512 half-bytes of ram built into the controller
+pub const MBC2_RAM_SIZE: usize = 0x200;
+
+/// Bit 8 of the address tells the ram enable and rom bank registers apart
+const REGISTER_SELECT_BIT: usize = 0x0100;
+
+pub struct Mbc2 {
+	ram_enabled: bool,
+	/// 4 bits, for up to 256 KiB of rom
+	rom_bank: u8,
+}
+
+impl Default for Mbc2 {
+	fn default() -> Self {
+		Mbc2 {
+			ram_enabled: false,
+			rom_bank: 1,
+		}
+	}
+}
+
+impl BankController for Mbc2 {
+	fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+		match address {
+			0x0000 ..= 0x3FFF => rom_bank_byte(rom, 0, address),
+			_ => rom_bank_byte(rom, self.rom_bank as usize, address),
+		}
+	}
+
+	fn write_rom(&mut self, address: usize, data: u8) {
+		match address {
+			0x0000 ..= 0x3FFF if address & REGISTER_SELECT_BIT == 0 => {
+				self.ram_enabled = data & 0x0F == 0x0A;
+			},
+			0x0000 ..= 0x3FFF => {
+				let bank = data & 0x0F;
+				self.rom_bank = if bank == 0 { 1 } else { bank };
+			},
+			_ => {},
+		}
+	}
+
+	/// Only the lower nibble exists, the upper one reads as 1's
+	/// The 512 bytes are mirrored across the whole 0xA000-0xBFFF area
+	fn read_ram(&self, ram: &[u8], address: usize) -> u8 {
+		if !self.ram_enabled {
+			return 0xFF
+		}
+		ram[address & (MBC2_RAM_SIZE - 1)] | 0xF0
+	}
+
+	fn write_ram(&mut self, ram: &mut [u8], address: usize, data: u8) -> bool {
+		if self.ram_enabled {
+			ram[address & (MBC2_RAM_SIZE - 1)] = data & 0x0F;
+		}
+		self.ram_enabled
+	}
+
+	fn built_in_ram_size(&self) -> Option<usize> {
+		Some(MBC2_RAM_SIZE)
+	}
+}
@@ -0,0 +1,84 @@
+use super::mbc::*;
+use super::rtc::*;
+
+pub struct Mbc3 {
+	/// Enables both the ram and the clock registers
+	ram_enabled: bool,
+	/// 7 bits, for up to 2 MiB of rom
+	rom_bank: u8,
+	/// 0x00-0x03 selects a ram bank, 0x08-0x0C a clock register
+	ram_bank: u8,
+	rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+	pub fn new(rtc: Option<Rtc>) -> Self {
+		Mbc3 {
+			ram_enabled: false,
+			rom_bank: 1,
+			ram_bank: 0,
+			rtc,
+		}
+	}
+}
+
+impl BankController for Mbc3 {
+	fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+		match address {
+			0x0000 ..= 0x3FFF => rom_bank_byte(rom, 0, address),
+			_ => rom_bank_byte(rom, self.rom_bank as usize, address),
+		}
+	}
+
+	fn write_rom(&mut self, address: usize, data: u8) {
+		match address {
+			0x0000 ..= 0x1FFF => self.ram_enabled = data & 0x0F == 0x0A,
+			0x2000 ..= 0x3FFF => {
+				let bank = data & 0x7F;
+				self.rom_bank = if bank == 0 { 1 } else { bank };
+			},
+			0x4000 ..= 0x5FFF => self.ram_bank = data,
+			_ => {
+				if let Some(rtc) = &mut self.rtc {
+					rtc.write_latch(data);
+				}
+			},
+		}
+	}
+
+	fn read_ram(&self, ram: &[u8], address: usize) -> u8 {
+		if !self.ram_enabled {
+			return 0xFF
+		}
+		match (self.ram_bank, &self.rtc) {
+			(0x00 ..= 0x03, _) => ram_bank_byte(ram, self.ram_bank as usize, address),
+			(0x08 ..= 0x0C, Some(rtc)) => rtc.read(self.ram_bank),
+			_ => 0xFF,
+		}
+	}
+
+	fn write_ram(&mut self, ram: &mut [u8], address: usize, data: u8) -> bool {
+		if !self.ram_enabled {
+			return false
+		}
+		match (self.ram_bank, &mut self.rtc) {
+			(0x00 ..= 0x03, _) => write_ram_bank(ram, self.ram_bank as usize, address, data),
+			// The clock gets saved whether it was written to or not
+			(0x08 ..= 0x0C, Some(rtc)) => {
+				rtc.write(self.ram_bank, data);
+				false
+			},
+			_ => false,
+		}
+	}
+
+	fn tick(&mut self, cycles: u32) {
+		if let Some(rtc) = &mut self.rtc {
+			rtc.tick(cycles);
+		}
+	}
+
+	fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+		self.rtc.as_mut()
+	}
+}
@@ -0,0 +1,75 @@
+use super::mbc::*;
+
+/// On rumble cartridges this bit of the ram bank register drives the motor
+const RUMBLE_BIT: u8 = 0b1000;
+
+pub struct Mbc5 {
+	ram_enabled: bool,
+	/// 9 bits, unlike older controllers bank 0 can be mapped at 0x4000
+	rom_bank: u16,
+	ram_bank: u8,
+	rumble: bool,
+	motor_on: bool,
+	on_rumble: Option<RumbleHandler>,
+}
+
+impl Mbc5 {
+	pub fn new(rumble: bool) -> Self {
+		Mbc5 {
+			ram_enabled: false,
+			rom_bank: 1,
+			ram_bank: 0,
+			rumble,
+			motor_on: false,
+			on_rumble: None,
+		}
+	}
+}
+
+impl BankController for Mbc5 {
+	fn read_rom(&self, rom: &[u8], address: usize) -> u8 {
+		match address {
+			0x0000 ..= 0x3FFF => rom_bank_byte(rom, 0, address),
+			_ => rom_bank_byte(rom, self.rom_bank as usize, address),
+		}
+	}
+
+	fn write_rom(&mut self, address: usize, data: u8) {
+		match address {
+			0x0000 ..= 0x1FFF => self.ram_enabled = data & 0x0F == 0x0A,
+			0x2000 ..= 0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | data as u16,
+			0x3000 ..= 0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((data & 0b1) as u16) << 8,
+			0x4000 ..= 0x5FFF => {
+				if !self.rumble {
+					self.ram_bank = data & 0x0F;
+					return
+				}
+
+				self.ram_bank = data & 0b111;
+				let motor_on = data & RUMBLE_BIT != 0;
+				if motor_on != self.motor_on {
+					self.motor_on = motor_on;
+					if let Some(handler) = &mut self.on_rumble {
+						handler(motor_on);
+					}
+				}
+			},
+			_ => {},
+		}
+	}
+
+	fn read_ram(&self, ram: &[u8], address: usize) -> u8 {
+		if !self.ram_enabled {
+			return 0xFF
+		}
+		ram_bank_byte(ram, self.ram_bank as usize, address)
+	}
+
+	fn write_ram(&mut self, ram: &mut [u8], address: usize, data: u8) -> bool {
+		self.ram_enabled && write_ram_bank(ram, self.ram_bank as usize, address, data)
+	}
+
+	fn set_rumble_handler(&mut self, handler: RumbleHandler) {
+		self.on_rumble = Some(handler);
+	}
+}
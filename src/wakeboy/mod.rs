@@ -6,4 +6,22 @@ pub mod registers;
 pub mod cpu;
 pub mod flags;
 pub mod bus;
-pub mod aluops;
\ No newline at end of file
+pub mod io;
+pub mod dma;
+pub mod ppu;
+pub mod sprite;
+pub mod fifo;
+pub mod aluops;
+pub mod alu;
+pub mod interrupts;
+pub mod timer;
+pub mod cartridge;
+pub mod header;
+pub mod mbc;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod rtc;
+pub mod save;
+pub mod screenshot;
@@ -0,0 +1,674 @@
+use super::interrupts::*;
+use super::io;
+use super::sprite::*;
+use super::fifo::*;
+
+pub const VRAM_SIZE: usize = 0x2000;
+pub const OAM_SIZE: usize = 0xA0;
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// One M-cycle is 4 dots
+const DOTS_PER_CYCLE: u32 = 4;
+const DOTS_PER_LINE: u32 = 456;
+const OAM_SCAN_DOTS: u32 = 80;
+const PIXEL_TRANSFER_DOTS: u32 = 172;
+const VBLANK_FIRST_LINE: u8 = 144;
+const LINES_PER_FRAME: u8 = 154;
+
+const LCDC_BG_ENABLE: u8 = 0b0000_0001;
+const LCDC_OBJ_ENABLE: u8 = 0b0000_0010;
+const LCDC_OBJ_SIZE: u8 = 0b0000_0100;
+const LCDC_BG_MAP: u8 = 0b0000_1000;
+const LCDC_TILE_DATA: u8 = 0b0001_0000;
+const LCDC_WINDOW_ENABLE: u8 = 0b0010_0000;
+const LCDC_WINDOW_MAP: u8 = 0b0100_0000;
+const LCDC_ENABLE: u8 = 0b1000_0000;
+
+// Offsets into VRAM
+const TILE_MAP_LOW: usize = 0x1800;
+const TILE_MAP_HIGH: usize = 0x1C00;
+/// Tiles 0x00-0x7F of the signed addressing mode, 0x80-0xFF sit below it
+const SIGNED_TILE_BASE: usize = 0x1000;
+const TILE_SIZE: usize = 16;
+const TILE_MAP_WIDTH: usize = 32;
+/// WX is the window's position plus 7
+const WINDOW_X_OFFSET: usize = 7;
+
+const SPRITE_COUNT: usize = OAM_SIZE / SPRITE_SIZE;
+const SPRITES_PER_LINE: usize = 10;
+/// The FIFOs are stopped this long for each sprite, on top of waiting for
+/// the background fetcher to finish its tile
+const SPRITE_FETCH_DOTS: u8 = 6;
+/// Fetcher steps but the push take 2 dots
+const FETCHER_STEP_DOTS: u8 = 2;
+
+const STAT_COINCIDENCE: u8 = 0b0000_0100;
+const STAT_HBLANK_SOURCE: u8 = 0b0000_1000;
+const STAT_VBLANK_SOURCE: u8 = 0b0001_0000;
+const STAT_OAM_SOURCE: u8 = 0b0010_0000;
+const STAT_LYC_SOURCE: u8 = 0b0100_0000;
+
+/// How the PPU turns VRAM into pixels
+#[derive(Copy, Clone, Debug, std::cmp::PartialEq)]
+pub enum Renderer {
+	/// Draws each line in one go when mode 3 starts, registers changed
+	/// in the middle of the line are missed
+	Scanline,
+	/// Pushes pixels out one dot at a time through the pixel FIFOs, mode 3
+	/// gets longer for sprites and the fine scroll like on hardware
+	Fifo,
+}
+
+impl std::str::FromStr for Renderer {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"scanline" => Ok(Renderer::Scanline),
+			"fifo" => Ok(Renderer::Fifo),
+			_ => Err(format!("Unknown renderer ({}), expected scanline or fifo", s)),
+		}
+	}
+}
+
+/// Values are what the mode bits of STAT read
+#[derive(Copy, Clone, Debug, std::cmp::PartialEq)]
+pub enum Mode {
+	HBlank = 0,
+	VBlank = 1,
+	OamScan = 2,
+	PixelTransfer = 3,
+}
+
+pub struct Ppu {
+	pub vram: [u8; VRAM_SIZE],
+	pub oam: [u8; OAM_SIZE],
+	lcdc: u8,
+	/// Only the interrupt source bits, the rest of STAT is computed on read
+	stat: u8,
+	scy: u8,
+	scx: u8,
+	/// Line being drawn, LY only differs from it on line 153
+	line: u8,
+	ly: u8,
+	lyc: u8,
+	bgp: u8,
+	obp0: u8,
+	obp1: u8,
+	wy: u8,
+	wx: u8,
+	mode: Mode,
+	/// Dots into the current line
+	dot: u32,
+	/// The first line after the LCD gets turned on has no OAM scan
+	first_line: bool,
+	/// OR of every enabled STAT source, the interrupt only fires when it
+	/// goes from low to high so overlapping sources block each other
+	stat_line: bool,
+	/// LY matched WY at some point this frame, the window can show from now on
+	window_triggered: bool,
+	/// Line of the window to draw next, only moves on lines the window was drawn on
+	window_line: u8,
+	/// One 2-bit shade per pixel, row by row
+	framebuffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+	renderer: Renderer,
+	/// Line being drawn by the FIFO renderer
+	fifo: PixelFifo,
+	/// Frames since power on
+	frames: u64,
+}
+
+impl Ppu {
+	/// Advances the PPU by one M-cycle
+	pub fn tick(&mut self, interrupts: &mut InterruptController) {
+		if !self.is_enabled() {
+			// Frames keep being counted with the LCD off, so that whatever waits
+			// on them doesn't wait forever
+			self.dot += DOTS_PER_CYCLE;
+			if self.dot >= DOTS_PER_LINE * LINES_PER_FRAME as u32 {
+				self.dot = 0;
+				self.frames += 1;
+			}
+			return
+		}
+
+		for _ in 0..DOTS_PER_CYCLE {
+			self.step_dot();
+		}
+		// LY already reads 0 a few dots into the last line
+		if self.line == LINES_PER_FRAME - 1 && self.dot >= DOTS_PER_CYCLE {
+			self.ly = 0;
+		}
+
+		let mode = self.current_mode();
+		if mode != self.mode {
+			self.mode = mode;
+			match mode {
+				Mode::PixelTransfer if self.renderer == Renderer::Scanline => self.render_line(),
+				Mode::VBlank => {
+					self.window_triggered = false;
+					self.window_line = 0;
+					self.frames += 1;
+					interrupts.request(Interrupt::VBlank);
+				},
+				_ => {},
+			}
+		}
+		self.update_stat_line(interrupts);
+	}
+
+	/// Takes effect from the next line
+	pub fn set_renderer(&mut self, renderer: Renderer) {
+		self.renderer = renderer;
+		self.fifo.drawing = false;
+	}
+
+	/// Goes up when VBlank starts, the framebuffer then holds a whole frame
+	pub fn frame_count(&self) -> u64 {
+		self.frames
+	}
+
+	/// Always HBlank while the LCD is off
+	pub fn mode(&self) -> Mode {
+		self.mode
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.lcdc & LCDC_ENABLE != 0
+	}
+
+	/// Last frame drawn, complete once VBlank starts. Shades go from 0 (white)
+	/// to 3 (black), `SCREEN_WIDTH` pixels per row
+	pub fn framebuffer(&self) -> &[u8] {
+		&self.framebuffer
+	}
+
+	pub fn read(&self, address: usize) -> u8 {
+		match address {
+			io::LCDC => self.lcdc,
+			io::STAT => {
+				let coincidence = if self.ly == self.lyc { STAT_COINCIDENCE } else { 0 };
+				self.stat | coincidence | self.mode as u8
+			},
+			io::SCY => self.scy,
+			io::SCX => self.scx,
+			io::LY => self.ly,
+			io::LYC => self.lyc,
+			io::BGP => self.bgp,
+			io::OBP0 => self.obp0,
+			io::OBP1 => self.obp1,
+			io::WY => self.wy,
+			io::WX => self.wx,
+			_ => 0xFF,
+		}
+	}
+
+	/// Registers as the boot rom leaves them, with the LCD on
+	pub fn skip_boot(&mut self) {
+		self.write_lcdc(0x91);
+		self.bgp = 0xFC;
+		self.obp0 = 0xFF;
+		self.obp1 = 0xFF;
+	}
+
+	/// Interrupts are needed as changing LYC or the STAT sources can raise the STAT line
+	pub fn write(&mut self, address: usize, data: u8, interrupts: &mut InterruptController) {
+		match address {
+			io::LCDC => self.write_lcdc(data),
+			io::STAT => self.stat = data,
+			io::SCY => self.scy = data,
+			io::SCX => self.scx = data,
+			io::LYC => self.lyc = data,
+			io::BGP => self.bgp = data,
+			io::OBP0 => self.obp0 = data,
+			io::OBP1 => self.obp1 = data,
+			io::WY => self.wy = data,
+			io::WX => self.wx = data,
+			_ => return,
+		}
+		if self.is_enabled() {
+			self.update_stat_line(interrupts);
+		}
+	}
+
+	fn write_lcdc(&mut self, data: u8) {
+		let was_enabled = self.is_enabled();
+		self.lcdc = data;
+		match (was_enabled, self.is_enabled()) {
+			// Turned off, the screen goes blank and LY stays at 0
+			(true, false) => {
+				self.line = 0;
+				self.ly = 0;
+				self.dot = 0;
+				self.mode = Mode::HBlank;
+				self.stat_line = false;
+				self.window_triggered = false;
+				self.window_line = 0;
+				self.fifo.drawing = false;
+				self.framebuffer = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+			},
+			(false, true) => {
+				self.dot = 0;
+				self.first_line = true;
+			},
+			_ => {},
+		}
+	}
+
+	fn step_dot(&mut self) {
+		self.dot += 1;
+		if self.dot == DOTS_PER_LINE {
+			self.dot = 0;
+			self.line = (self.line + 1) % LINES_PER_FRAME;
+			self.ly = self.line;
+			self.first_line = false;
+		}
+
+		if self.renderer == Renderer::Fifo && self.line < VBLANK_FIRST_LINE {
+			if self.dot == OAM_SCAN_DOTS {
+				self.check_window_trigger();
+				self.fifo = PixelFifo::new(self.scx % 8, self.line_sprites());
+			} else if self.fifo.drawing {
+				self.fifo_step();
+			}
+		}
+	}
+
+	fn current_mode(&self) -> Mode {
+		let drawing = match self.renderer {
+			Renderer::Scanline => self.dot < OAM_SCAN_DOTS + PIXEL_TRANSFER_DOTS,
+			Renderer::Fifo => self.fifo.drawing,
+		};
+
+		if self.line >= VBLANK_FIRST_LINE {
+			Mode::VBlank
+		} else if self.dot < OAM_SCAN_DOTS {
+			if self.first_line { Mode::HBlank } else { Mode::OamScan }
+		} else if drawing {
+			Mode::PixelTransfer
+		} else {
+			Mode::HBlank
+		}
+	}
+
+	fn check_window_trigger(&mut self) {
+		if self.ly == self.wy {
+			self.window_triggered = true;
+		}
+	}
+
+	/// One dot of mode 3 for the FIFO renderer: sprites and the window can
+	/// interrupt the fetcher, otherwise it runs and a pixel gets pushed out
+	fn fifo_step(&mut self) {
+		if !self.fifo.in_window && self.is_window_at(self.fifo.x) {
+			self.fifo.start_window();
+			// A WX below 7 hides the left of the window off screen
+			self.fifo.discard = if self.fifo.x == 0 {
+				(WINDOW_X_OFFSET as u8).saturating_sub(self.wx)
+			} else {
+				0
+			};
+		}
+
+		let x = self.fifo.x as i16;
+		if self.fifo.sprite_dots.is_none() {
+			match self.fifo.sprites.front() {
+				Some(sprite) if sprite.x <= x => {
+					if self.lcdc & LCDC_OBJ_ENABLE != 0 {
+						self.fifo.sprite_dots = Some(0);
+					} else {
+						self.fifo.sprites.pop_front();
+					}
+				},
+				_ => {},
+			}
+		}
+
+		if let Some(dots) = self.fifo.sprite_dots {
+			if self.fifo.bg.is_empty() || self.fifo.is_fetching() {
+				self.fetcher_step();
+			} else if dots + 1 < SPRITE_FETCH_DOTS {
+				self.fifo.sprite_dots = Some(dots + 1);
+			} else if let Some(sprite) = self.fifo.sprites.pop_front() {
+				let (low, high) = self.sprite_row(&sprite);
+				self.fifo.merge_sprite_row(&sprite, low, high, (x - sprite.x) as usize);
+				self.fifo.sprite_dots = None;
+			}
+			return
+		}
+
+		self.fetcher_step();
+		self.fifo_pop();
+	}
+
+	fn fetcher_step(&mut self) {
+		if self.fifo.step != FetcherStep::Push {
+			self.fifo.step_dots += 1;
+			if self.fifo.step_dots < FETCHER_STEP_DOTS {
+				return
+			}
+		}
+
+		match self.fifo.step {
+			FetcherStep::Tile => {
+				self.fifo.tile = self.vram[self.fetcher_tile_map_address()];
+				self.fifo.next_step(FetcherStep::DataLow);
+			},
+			FetcherStep::DataLow => {
+				self.fifo.low = self.vram[self.fetcher_data_address()];
+				self.fifo.next_step(FetcherStep::DataHigh);
+			},
+			// The push is tried right away when the high byte comes in
+			FetcherStep::DataHigh => {
+				self.fifo.high = self.vram[self.fetcher_data_address() + 1];
+				self.fifo.next_step(FetcherStep::Push);
+				self.fetcher_push();
+			},
+			FetcherStep::Push => self.fetcher_push(),
+		}
+	}
+
+	fn fetcher_push(&mut self) {
+		if !self.fifo.bg.is_empty() {
+			return
+		}
+		if self.fifo.first_fetch {
+			self.fifo.first_fetch = false;
+		} else {
+			self.fifo.push_bg_row();
+		}
+		self.fifo.next_step(FetcherStep::Tile);
+	}
+
+	/// SCX, SCY and LCDC are read again on every fetch
+	fn fetcher_tile_map_address(&self) -> usize {
+		let column = self.fifo.fetch_x as usize;
+		if self.fifo.in_window {
+			let map = if self.lcdc & LCDC_WINDOW_MAP != 0 { TILE_MAP_HIGH } else { TILE_MAP_LOW };
+			map + (self.window_line as usize / 8) * TILE_MAP_WIDTH + column % TILE_MAP_WIDTH
+		} else {
+			let map = if self.lcdc & LCDC_BG_MAP != 0 { TILE_MAP_HIGH } else { TILE_MAP_LOW };
+			let y = (self.line as usize + self.scy as usize) & 0xFF;
+			map + (y / 8) * TILE_MAP_WIDTH + (self.scx as usize / 8 + column) % TILE_MAP_WIDTH
+		}
+	}
+
+	fn fetcher_data_address(&self) -> usize {
+		let y = if self.fifo.in_window {
+			self.window_line as usize
+		} else {
+			self.line as usize + self.scy as usize
+		};
+		self.tile_address(self.fifo.tile) + (y % 8) * 2
+	}
+
+	/// Palettes and LCDC are applied as the pixel leaves, so changing them
+	/// mid-line shows on the screen
+	fn fifo_pop(&mut self) {
+		let bg_color = match self.fifo.bg.pop_front() {
+			Some(color) => color,
+			None => return,
+		};
+		if self.fifo.discard > 0 {
+			self.fifo.discard -= 1;
+			return
+		}
+
+		let obj = self.fifo.obj.pop_front();
+		let bg_color = if self.lcdc & LCDC_BG_ENABLE == 0 { 0 } else { bg_color };
+		let shade = match obj {
+			Some(pixel) if pixel.color != 0 &&
+				self.lcdc & LCDC_OBJ_ENABLE != 0 &&
+				!(pixel.behind_bg && bg_color != 0) => {
+				let palette = if pixel.obp1 { self.obp1 } else { self.obp0 };
+				palette_shade(palette, pixel.color)
+			},
+			_ => palette_shade(self.bgp, bg_color),
+		};
+
+		self.framebuffer[self.line as usize * SCREEN_WIDTH + self.fifo.x] = shade;
+		self.fifo.x += 1;
+		if self.fifo.x == SCREEN_WIDTH {
+			self.fifo.drawing = false;
+			if self.fifo.in_window {
+				self.window_line = self.window_line.wrapping_add(1);
+			}
+		}
+	}
+
+	fn render_line(&mut self) {
+		self.check_window_trigger();
+
+		let row = self.line as usize * SCREEN_WIDTH;
+		let mut bg_colors = [0; SCREEN_WIDTH];
+		let mut window_drawn = false;
+		for (x, bg_color) in bg_colors.iter_mut().enumerate() {
+			// On the DMG this bit blanks both the background and the window
+			*bg_color = if self.lcdc & LCDC_BG_ENABLE == 0 {
+				0
+			} else if self.is_window_at(x) {
+				window_drawn = true;
+				self.window_color(x)
+			} else {
+				self.background_color(x)
+			};
+			self.framebuffer[row + x] = palette_shade(self.bgp, *bg_color);
+		}
+
+		if window_drawn {
+			self.window_line = self.window_line.wrapping_add(1);
+		}
+
+		if self.lcdc & LCDC_OBJ_ENABLE != 0 {
+			self.render_sprites(&bg_colors);
+		}
+	}
+
+	fn render_sprites(&mut self, bg_colors: &[u8; SCREEN_WIDTH]) {
+		let sprites = self.line_sprites();
+		let row = self.line as usize * SCREEN_WIDTH;
+		for (x, bg_color) in bg_colors.iter().enumerate() {
+			// The first opaque pixel wins, even when the background then hides it
+			let pixel = sprites.iter()
+				.find_map(|sprite| self.sprite_color(sprite, x as i16).map(|color| (sprite, color)));
+			if let Some((sprite, color)) = pixel {
+				if sprite.behind_bg() && *bg_color != 0 {
+					continue
+				}
+				let palette = if sprite.uses_obp1() { self.obp1 } else { self.obp0 };
+				self.framebuffer[row + x] = palette_shade(palette, color);
+			}
+		}
+	}
+
+	/// The first 10 sprites in OAM order covering the line, sorted by drawing
+	/// priority: on the DMG the leftmost one is on top, OAM order breaks ties
+	fn line_sprites(&self) -> Vec<Sprite> {
+		let line = self.line as i16;
+		let height = self.sprite_height();
+		let mut sprites: Vec<Sprite> = (0..SPRITE_COUNT)
+			.map(|i| Sprite::from_oam(&self.oam, i))
+			.filter(|sprite| line >= sprite.y && line < sprite.y + height)
+			.take(SPRITES_PER_LINE)
+			.collect();
+		// Stable, so OAM order is kept for sprites on the same X
+		sprites.sort_by_key(|sprite| sprite.x);
+		sprites
+	}
+
+	fn sprite_height(&self) -> i16 {
+		if self.lcdc & LCDC_OBJ_SIZE != 0 { 16 } else { 8 }
+	}
+
+	/// Color index of the sprite at screen column `x` on the current line,
+	/// None where it's transparent or doesn't cover `x`
+	fn sprite_color(&self, sprite: &Sprite, x: i16) -> Option<u8> {
+		if x < sprite.x || x >= sprite.x + 8 {
+			return None
+		}
+
+		let (low, high) = self.sprite_row(sprite);
+		match sprite.row_color(low, high, (x - sprite.x) as usize) {
+			0 => None,
+			color => Some(color),
+		}
+	}
+
+	/// Tile data of the sprite's row on the current line, the Y flip applied
+	fn sprite_row(&self, sprite: &Sprite) -> (u8, u8) {
+		let height = self.sprite_height();
+		// LCDC can shrink the sprites after they were picked for the line
+		let mut y = (self.line as i16 - sprite.y) & (height - 1);
+		if sprite.y_flip() {
+			y = height - 1 - y;
+		}
+
+		// 8x16 sprites are two consecutive tiles, the low bit of the index is ignored
+		let tile = if height == 16 { sprite.tile & 0xFE } else { sprite.tile };
+		let address = tile as usize * TILE_SIZE + y as usize * 2;
+		(self.vram[address], self.vram[address + 1])
+	}
+
+	fn is_window_at(&self, x: usize) -> bool {
+		self.lcdc & LCDC_WINDOW_ENABLE != 0 &&
+			self.window_triggered &&
+			x + WINDOW_X_OFFSET >= self.wx as usize
+	}
+
+	fn background_color(&self, x: usize) -> u8 {
+		let map = if self.lcdc & LCDC_BG_MAP != 0 { TILE_MAP_HIGH } else { TILE_MAP_LOW };
+		let x = (x + self.scx as usize) & 0xFF;
+		let y = (self.line as usize + self.scy as usize) & 0xFF;
+		self.tile_map_color(map, x, y)
+	}
+
+	fn window_color(&self, x: usize) -> u8 {
+		let map = if self.lcdc & LCDC_WINDOW_MAP != 0 { TILE_MAP_HIGH } else { TILE_MAP_LOW };
+		let x = x + WINDOW_X_OFFSET - self.wx as usize;
+		self.tile_map_color(map, x, self.window_line as usize)
+	}
+
+	/// Color index (before the palette) at pixel `x`, `y` of a 256x256 tile map
+	fn tile_map_color(&self, map: usize, x: usize, y: usize) -> u8 {
+		let tile = self.vram[map + (y / 8) * TILE_MAP_WIDTH + x / 8];
+		self.tile_color(self.tile_address(tile), x % 8, y % 8)
+	}
+
+	/// Background and window tiles, LCDC picks signed or unsigned indexes
+	fn tile_address(&self, tile: u8) -> usize {
+		if self.lcdc & LCDC_TILE_DATA != 0 {
+			tile as usize * TILE_SIZE
+		} else {
+			(SIGNED_TILE_BASE as isize + (tile as i8) as isize * TILE_SIZE as isize) as usize
+		}
+	}
+
+	/// Each row of a tile is 2 bytes, the first one holds the low bit of every pixel
+	fn tile_color(&self, tile_address: usize, x: usize, y: usize) -> u8 {
+		let low = self.vram[tile_address + y * 2];
+		let high = self.vram[tile_address + y * 2 + 1];
+		let bit = 7 - x;
+		((high >> bit) & 1) << 1 | ((low >> bit) & 1)
+	}
+
+	fn update_stat_line(&mut self, interrupts: &mut InterruptController) {
+		let mut sources = match self.mode {
+			Mode::HBlank => STAT_HBLANK_SOURCE,
+			// The OAM source also fires when VBlank starts
+			Mode::VBlank if self.line == VBLANK_FIRST_LINE && self.dot < DOTS_PER_CYCLE =>
+				STAT_VBLANK_SOURCE | STAT_OAM_SOURCE,
+			Mode::VBlank => STAT_VBLANK_SOURCE,
+			Mode::OamScan => STAT_OAM_SOURCE,
+			Mode::PixelTransfer => 0,
+		};
+		if self.ly == self.lyc {
+			sources |= STAT_LYC_SOURCE;
+		}
+
+		let stat_line = self.stat & sources != 0;
+		if stat_line && !self.stat_line {
+			interrupts.request(Interrupt::Stat);
+		}
+		self.stat_line = stat_line;
+	}
+}
+
+impl Default for Ppu {
+	fn default() -> Self {
+		Ppu {
+			vram: [0; VRAM_SIZE],
+			oam: [0; OAM_SIZE],
+			lcdc: 0,
+			stat: 0,
+			scy: 0,
+			scx: 0,
+			line: 0,
+			ly: 0,
+			lyc: 0,
+			bgp: 0,
+			obp0: 0,
+			obp1: 0,
+			wy: 0,
+			wx: 0,
+			mode: Mode::HBlank,
+			dot: 0,
+			first_line: false,
+			stat_line: false,
+			window_triggered: false,
+			window_line: 0,
+			framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+			renderer: Renderer::Scanline,
+			fifo: Default::default(),
+			frames: 0,
+		}
+	}
+}
+
+/// Palettes hold a 2-bit shade for each of the 4 color indexes
+fn palette_shade(palette: u8, color: u8) -> u8 {
+	(palette >> (color * 2)) & 0b11
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CYCLES_PER_FRAME: u32 = DOTS_PER_LINE * LINES_PER_FRAME as u32 / DOTS_PER_CYCLE;
+
+	fn fifo_ppu(lcdc: u8) -> Ppu {
+		let mut ppu: Ppu = Default::default();
+		ppu.set_renderer(Renderer::Fifo);
+		ppu.lcdc = lcdc;
+		ppu
+	}
+
+	fn set_sprite(ppu: &mut Ppu, index: usize, y: u8, x: u8, attributes: u8) {
+		ppu.oam[index * SPRITE_SIZE..(index + 1) * SPRITE_SIZE].copy_from_slice(&[y, x, 0, attributes]);
+	}
+
+	#[test]
+	fn close_sprites_dont_stall_the_fifo() {
+		for gap in 1..8 {
+			let mut ppu = fifo_ppu(0x93);
+			set_sprite(&mut ppu, 0, 16, 10, 0);
+			set_sprite(&mut ppu, 1, 16, 10 + gap, 0);
+			let mut interrupts: InterruptController = Default::default();
+			for _ in 0..CYCLES_PER_FRAME * 2 {
+				ppu.tick(&mut interrupts);
+			}
+			assert!(ppu.frame_count() > 0, "stalled with sprites {} px apart", gap);
+		}
+	}
+
+	#[test]
+	fn sprite_row_survives_smaller_sprites_mid_line() {
+		let mut ppu = fifo_ppu(0x97);
+		set_sprite(&mut ppu, 0, 16, 8, 0b0100_0000);
+		ppu.line = 12;
+		let sprite = ppu.line_sprites()[0];
+		ppu.vram[3 * 2] = 0xAA;
+		ppu.lcdc = 0x93;
+		assert_eq!(ppu.sprite_row(&sprite).0, 0xAA);
+	}
+}
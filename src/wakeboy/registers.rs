@@ -1,4 +1,6 @@
 use super::core::*;
+use super::flags::*;
+use super::bus::*;
 
 #[derive(Copy, Clone, std::cmp::PartialEq)]
 pub enum Reg8 {
@@ -20,11 +22,74 @@ pub struct Registers {
 	pub c: u8,
 	pub d: u8,
 	pub e: u8,
-	pub f: u8,
+	pub f: RegFlags,
+	pub h: u8,
+	pub l: u8,
 	pub sp: u16,
 	pub pc: u16,
 }
 
+impl Registers {
+	/// (HL) is resolved through the memory bus
+	pub fn get_reg8(&self, reg: Reg8, memory: &MemoryBus) -> u8 {
+		match reg {
+			Reg8::A => self.a,
+			Reg8::B => self.b,
+			Reg8::C => self.c,
+			Reg8::D => self.d,
+			Reg8::E => self.e,
+			Reg8::H => self.h,
+			Reg8::L => self.l,
+			Reg8::MHL => memory.get_byte(self.get_reg16(Reg16::HL) as usize),
+			Reg8::Invalid => {
+				warn_or_crash(String::from("Program tried to read an invalid register"));
+				0xFF
+			}
+		}
+	}
+
+	pub fn write_reg8(&mut self, reg: Reg8, value: u8, memory: &mut MemoryBus) {
+		match reg {
+			Reg8::A => self.a = value,
+			Reg8::B => self.b = value,
+			Reg8::C => self.c = value,
+			Reg8::D => self.d = value,
+			Reg8::E => self.e = value,
+			Reg8::H => self.h = value,
+			Reg8::L => self.l = value,
+			Reg8::MHL => memory.write_byte(self.get_reg16(Reg16::HL) as usize, value),
+			Reg8::Invalid => warn_or_crash(String::from("Program tried to write an invalid register")),
+		}
+	}
+
+	/// Pairs are big-endian: the first register named is the high byte
+	pub fn get_reg16(&self, reg: Reg16) -> u16 {
+		match reg {
+			Reg16::AF => combine_bytes(self.f.into(), self.a),
+			Reg16::BC => combine_bytes(self.c, self.b),
+			Reg16::DE => combine_bytes(self.e, self.d),
+			Reg16::HL => combine_bytes(self.l, self.h),
+			Reg16::SP => self.sp,
+			Reg16::Invalid => {
+				warn_or_crash(String::from("Program tried to read an invalid register"));
+				0xFFFF
+			}
+		}
+	}
+
+	pub fn write_reg16(&mut self, reg: Reg16, value: u16) {
+		let (high, low) = ((value >> 8) as u8, value as u8);
+		match reg {
+			Reg16::AF => { self.a = high; self.f = RegFlags::from(low); },
+			Reg16::BC => { self.b = high; self.c = low; },
+			Reg16::DE => { self.d = high; self.e = low; },
+			Reg16::HL => { self.h = high; self.l = low; },
+			Reg16::SP => self.sp = value,
+			Reg16::Invalid => warn_or_crash(String::from("Program tried to write an invalid register")),
+		}
+	}
+}
+
 impl std::convert::From<u8> for Reg8 {
 	fn from(bits: u8) -> Self {
 		match bits {
@@ -51,7 +116,7 @@ impl Reg16 {
 			0b01 => Reg16::DE,
 			0b10 => Reg16::HL,
 			0b11 => {
-				if want_sp { return Reg16::SP } else { return Reg16::AF }
+				if want_sp { Reg16::SP } else { Reg16::AF }
 			}
 			_ => {
 				warn_or_crash(String::from("Program encountered invalid register denominator"));
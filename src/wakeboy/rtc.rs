@@ -0,0 +1,338 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// M-cycles in one second of emulated time
+const CYCLES_PER_SECOND: u32 = 1_048_576;
+
+const DAY_HIGH_BIT: u8 = 0b0000_0001;
+const HALT_BIT: u8 = 0b0100_0000;
+const DAY_CARRY_BIT: u8 = 0b1000_0000;
+
+/// Size of the clock footer appended to .sav files, as written by BGB and VBA-M:
+/// the 5 registers then the 5 latched ones as 32-bit values, then a 64-bit
+/// unix timestamp, all little-endian. Some emulators use a 32-bit timestamp
+pub const RTC_SAVE_SIZE: usize = 48;
+pub const RTC_SAVE_SIZE_SHORT: usize = 44;
+
+/// What the real-time clock follows
+#[derive(Copy, Clone, Debug, std::cmp::PartialEq)]
+pub enum RtcClock {
+	/// Host wall time, keeps running while the emulator is closed
+	Host,
+	/// CPU cycles, deterministic from one run to another
+	Emulated,
+}
+
+impl std::str::FromStr for RtcClock {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"host" => Ok(RtcClock::Host),
+			"emulated" => Ok(RtcClock::Emulated),
+			_ => Err(format!("Unknown clock ({}), expected host or emulated", s)),
+		}
+	}
+}
+
+/// MBC3 real-time clock, registers 0x08-0x0C once selected
+pub struct Rtc {
+	clock: RtcClock,
+	seconds: u8,
+	minutes: u8,
+	hours: u8,
+	/// 9-bit day counter
+	days: u16,
+	halted: bool,
+	day_carry: bool,
+	/// Snapshot of the registers taken on latch, which is what the game reads
+	latched: [u8; 5],
+	/// A 0x00 was written to the latch register, a 0x01 will latch
+	latch_armed: bool,
+	/// Emulated clock: M-cycles into the current second
+	cycles: u32,
+	/// Host clock: unix time of the last update
+	last_update: u64,
+}
+
+impl Rtc {
+	pub fn new(clock: RtcClock) -> Self {
+		Rtc {
+			clock,
+			seconds: 0,
+			minutes: 0,
+			hours: 0,
+			days: 0,
+			halted: false,
+			day_carry: false,
+			latched: [0; 5],
+			latch_armed: false,
+			cycles: 0,
+			last_update: host_time(),
+		}
+	}
+
+	pub fn tick(&mut self, cycles: u32) {
+		if self.clock != RtcClock::Emulated || self.halted {
+			return
+		}
+		self.cycles += cycles;
+		if self.cycles >= CYCLES_PER_SECOND {
+			let seconds = self.cycles / CYCLES_PER_SECOND;
+			self.cycles %= CYCLES_PER_SECOND;
+			self.advance(seconds as u64);
+		}
+	}
+
+	/// Catches up with the host clock, emulated time only moves through `tick`
+	pub fn update(&mut self) {
+		if self.clock != RtcClock::Host {
+			return
+		}
+		let now = host_time();
+		let elapsed = now.saturating_sub(self.last_update);
+		self.last_update = now;
+		self.advance(elapsed);
+	}
+
+	/// 0x00 followed by 0x01 copies the running clock into the latched registers
+	pub fn write_latch(&mut self, data: u8) {
+		if self.latch_armed && data == 0x01 {
+			self.update();
+			self.latched = self.registers();
+		}
+		self.latch_armed = data == 0x00;
+	}
+
+	/// `register` is the 0x08-0x0C value written to select it
+	pub fn read(&self, register: u8) -> u8 {
+		match register {
+			0x08 ..= 0x0C => self.latched[(register - 0x08) as usize],
+			_ => 0xFF,
+		}
+	}
+
+	pub fn write(&mut self, register: u8, data: u8) {
+		self.update();
+		match register {
+			0x08 => {
+				self.seconds = data & 0x3F;
+				self.cycles = 0;
+			},
+			0x09 => self.minutes = data & 0x3F,
+			0x0A => self.hours = data & 0x1F,
+			0x0B => self.days = (self.days & 0x100) | data as u16,
+			0x0C => {
+				self.days = (self.days & 0xFF) | ((data & DAY_HIGH_BIT) as u16) << 8;
+				self.halted = data & HALT_BIT != 0;
+				self.day_carry = data & DAY_CARRY_BIT != 0;
+			},
+			_ => return,
+		}
+		self.latched[(register - 0x08) as usize] = self.registers()[(register - 0x08) as usize];
+	}
+
+	pub fn save_state(&mut self) -> Vec<u8> {
+		self.update();
+		let mut data = Vec::with_capacity(RTC_SAVE_SIZE);
+		for register in self.registers().iter().chain(self.latched.iter()) {
+			data.extend_from_slice(&(*register as u32).to_le_bytes());
+		}
+		let timestamp = if self.clock == RtcClock::Host { self.last_update } else { host_time() };
+		data.extend_from_slice(&timestamp.to_le_bytes());
+		data
+	}
+
+	/// With the host clock, the time spent since the state was saved gets added
+	pub fn load_state(&mut self, data: &[u8]) {
+		if data.len() != RTC_SAVE_SIZE && data.len() != RTC_SAVE_SIZE_SHORT {
+			return
+		}
+
+		let field = |i: usize| data[i * 4];
+		self.seconds = field(0) & 0x3F;
+		self.minutes = field(1) & 0x3F;
+		self.hours = field(2) & 0x1F;
+		self.days = field(3) as u16 | ((field(4) & DAY_HIGH_BIT) as u16) << 8;
+		self.halted = field(4) & HALT_BIT != 0;
+		self.day_carry = field(4) & DAY_CARRY_BIT != 0;
+		for (i, latched) in self.latched.iter_mut().enumerate() {
+			*latched = field(5 + i);
+		}
+
+		let mut timestamp = [0; 8];
+		timestamp[..data.len() - 40].copy_from_slice(&data[40..]);
+		self.last_update = u64::from_le_bytes(timestamp);
+		self.update();
+	}
+
+	fn registers(&self) -> [u8; 5] {
+		[
+			self.seconds,
+			self.minutes,
+			self.hours,
+			self.days as u8,
+			(self.days >> 8) as u8 & DAY_HIGH_BIT |
+				if self.halted { HALT_BIT } else { 0 } |
+				if self.day_carry { DAY_CARRY_BIT } else { 0 },
+		]
+	}
+
+	fn advance(&mut self, mut seconds: u64) {
+		if self.halted {
+			return
+		}
+
+		// Out of range values (which games can write) count up to the register's
+		// limit and wrap to 0 without carrying, step through those one by one
+		while seconds > 0 && !self.is_in_range() {
+			self.tick_second();
+			seconds -= 1;
+		}
+		if seconds == 0 {
+			return
+		}
+
+		let total = seconds +
+			self.seconds as u64 +
+			self.minutes as u64 * 60 +
+			self.hours as u64 * 3600 +
+			self.days as u64 * 86400;
+		self.seconds = (total % 60) as u8;
+		self.minutes = (total / 60 % 60) as u8;
+		self.hours = (total / 3600 % 24) as u8;
+		let days = total / 86400;
+		if days > 0x1FF {
+			self.day_carry = true;
+		}
+		self.days = (days & 0x1FF) as u16;
+	}
+
+	fn is_in_range(&self) -> bool {
+		self.seconds < 60 && self.minutes < 60 && self.hours < 24
+	}
+
+	fn tick_second(&mut self) {
+		self.seconds = (self.seconds + 1) & 0x3F;
+		if self.seconds != 60 {
+			return
+		}
+		self.seconds = 0;
+		self.minutes = (self.minutes + 1) & 0x3F;
+		if self.minutes != 60 {
+			return
+		}
+		self.minutes = 0;
+		self.hours = (self.hours + 1) & 0x1F;
+		if self.hours != 24 {
+			return
+		}
+		self.hours = 0;
+		self.days += 1;
+		if self.days > 0x1FF {
+			self.days = 0;
+			self.day_carry = true;
+		}
+	}
+}
+
+fn host_time() -> u64 {
+	match SystemTime::now().duration_since(UNIX_EPOCH) {
+		Ok(d) => d.as_secs(),
+		Err(_) => 0,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Seconds, minutes, hours, low day bits and the flags register
+	fn rtc_at(registers: [u8; 5]) -> Rtc {
+		let mut rtc = Rtc::new(RtcClock::Emulated);
+		for (i, data) in registers.iter().enumerate() {
+			rtc.write(0x08 + i as u8, *data);
+		}
+		rtc
+	}
+
+	fn latch(rtc: &mut Rtc) {
+		rtc.write_latch(0x00);
+		rtc.write_latch(0x01);
+	}
+
+	#[test]
+	fn rollover_carries_into_days() {
+		let mut rtc = rtc_at([59, 59, 23, 0xFF, 0x00]);
+		rtc.tick(CYCLES_PER_SECOND);
+		assert_eq!(rtc.registers(), [0, 0, 0, 0x00, DAY_HIGH_BIT]);
+	}
+
+	#[test]
+	fn day_overflow_sets_the_carry_until_cleared() {
+		let mut rtc = rtc_at([59, 59, 23, 0xFF, DAY_HIGH_BIT]);
+		rtc.tick(CYCLES_PER_SECOND);
+		assert_eq!(rtc.registers(), [0, 0, 0, 0x00, DAY_CARRY_BIT]);
+
+		rtc.advance(86400);
+		assert_eq!(rtc.registers(), [0, 0, 0, 0x01, DAY_CARRY_BIT]);
+		rtc.write(0x0C, 0x00);
+		assert_eq!(rtc.registers()[4], 0);
+	}
+
+	#[test]
+	fn out_of_range_values_wrap_without_carry() {
+		let mut rtc = rtc_at([63, 10, 0, 0, 0]);
+		rtc.tick(CYCLES_PER_SECOND);
+		assert_eq!(rtc.registers(), [0, 10, 0, 0, 0]);
+	}
+
+	#[test]
+	fn halted_clock_doesnt_advance() {
+		let mut rtc = rtc_at([0, 0, 0, 0, HALT_BIT]);
+		rtc.tick(CYCLES_PER_SECOND * 10);
+		assert_eq!(rtc.registers(), [0, 0, 0, 0, HALT_BIT]);
+	}
+
+	#[test]
+	fn latches_on_0_then_1() {
+		let mut rtc = rtc_at([5, 0, 0, 0, 0]);
+		rtc.tick(CYCLES_PER_SECOND);
+		assert_eq!(rtc.read(0x08), 5);
+
+		rtc.write_latch(0x01);
+		assert_eq!(rtc.read(0x08), 5);
+		rtc.write_latch(0x00);
+		rtc.write_latch(0x02);
+		rtc.write_latch(0x01);
+		assert_eq!(rtc.read(0x08), 5);
+
+		latch(&mut rtc);
+		assert_eq!(rtc.read(0x08), 6);
+	}
+
+	#[test]
+	fn save_state_round_trips() {
+		let mut rtc = rtc_at([12, 34, 5, 0x67, DAY_HIGH_BIT | DAY_CARRY_BIT]);
+		latch(&mut rtc);
+		rtc.write(0x08, 13);
+		let data = rtc.save_state();
+		assert_eq!(data.len(), RTC_SAVE_SIZE);
+		assert_eq!(data[0..4], [13, 0, 0, 0]);
+		assert_eq!(data[20..24], [13, 0, 0, 0]);
+		assert_eq!(data[36..40], [DAY_HIGH_BIT | DAY_CARRY_BIT, 0, 0, 0]);
+
+		for size in [RTC_SAVE_SIZE, RTC_SAVE_SIZE_SHORT] {
+			let mut loaded = Rtc::new(RtcClock::Emulated);
+			loaded.load_state(&data[..size]);
+			assert_eq!(loaded.registers(), rtc.registers(), "{} bytes", size);
+			assert_eq!(loaded.latched, rtc.latched, "{} bytes", size);
+		}
+	}
+
+	#[test]
+	fn load_state_ignores_unknown_sizes() {
+		let mut rtc = rtc_at([1, 2, 3, 4, 0]);
+		rtc.load_state(&[0xFF; 40]);
+		assert_eq!(rtc.registers(), [1, 2, 3, 4, 0]);
+	}
+}
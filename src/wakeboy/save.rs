@@ -0,0 +1,41 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use super::core::*;
+use super::cartridge::*;
+
+/// `<rom>.sav` next to the rom, or inside `save_dir` when one is given
+pub fn save_path(rom_path: &Path, save_dir: Option<&Path>) -> PathBuf {
+	let path = rom_path.with_extension("sav");
+	match (save_dir, path.file_name()) {
+		(Some(dir), Some(name)) => dir.join(name),
+		_ => path,
+	}
+}
+
+/// A missing save file just means the game was never saved
+pub fn load(cartridge: &mut Cartridge, path: &Path) {
+	if !cartridge.has_battery() {
+		return
+	}
+
+	match fs::read(path) {
+		Ok(data) => {
+			println!("Loading save file {}", path.display());
+			cartridge.load_save_data(&data);
+		},
+		Err(e) if e.kind() == ErrorKind::NotFound => {},
+		Err(e) => warn_or_crash(format!("Couldn't read save file {} ({})", path.display(), e)),
+	}
+}
+
+/// Only writes if there's something new since the last save
+pub fn write(cartridge: &mut Cartridge, path: &Path) {
+	if !cartridge.needs_saving() {
+		return
+	}
+
+	if let Err(e) = fs::write(path, cartridge.save_data()) {
+		warn_or_crash(format!("Couldn't write save file {} ({})", path.display(), e));
+	}
+}
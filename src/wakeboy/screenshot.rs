@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use super::cpu::CPU;
+use super::ppu::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+/// Colors given to the 4 shades, from white to black
+#[derive(Copy, Clone, Debug, std::cmp::PartialEq)]
+pub enum Palette {
+	Grey,
+	/// The tint of the original DMG screen
+	Green,
+}
+
+impl Palette {
+	fn colors(&self) -> [[u8; 3]; 4] {
+		match *self {
+			Palette::Grey => [[0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA], [0x55, 0x55, 0x55], [0x00, 0x00, 0x00]],
+			Palette::Green => [[0x9B, 0xBC, 0x0F], [0x8B, 0xAC, 0x0F], [0x30, 0x62, 0x30], [0x0F, 0x38, 0x0F]],
+		}
+	}
+}
+
+impl std::str::FromStr for Palette {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"grey" => Ok(Palette::Grey),
+			"green" => Ok(Palette::Green),
+			_ => Err(format!("Unknown palette ({}), expected grey or green", s)),
+		}
+	}
+}
+
+/// The screenshot is taken as soon as one of the set conditions is met
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Trigger {
+	/// Frames since power on, counted when VBlank starts
+	pub frames: Option<u64>,
+	/// Address of the next instruction to run
+	pub pc: Option<u16>,
+}
+
+impl Trigger {
+	pub fn is_reached(&self, cpu: &CPU) -> bool {
+		self.frames.is_some_and(|frames| cpu.memory.ppu.frame_count() >= frames) ||
+			self.pc == Some(cpu.registers.pc)
+	}
+}
+
+/// Runs `cpu` until `trigger` is met, then writes the screen to `path`.
+/// Never returns if the trigger has no condition set or is never met
+pub fn capture(cpu: &mut CPU, trigger: &Trigger, path: &Path, palette: Palette) -> io::Result<()> {
+	while !trigger.is_reached(cpu) {
+		cpu.step();
+	}
+	write_png(path, cpu.memory.ppu.framebuffer(), palette)
+}
+
+/// `framebuffer` holds 2-bit shades, as given by `Ppu::framebuffer`
+pub fn write_png(path: &Path, framebuffer: &[u8], palette: Palette) -> io::Result<()> {
+	let colors = palette.colors();
+	let pixels: Vec<u8> = framebuffer.iter()
+		.flat_map(|shade| colors[(*shade & 0b11) as usize].iter().copied())
+		.collect();
+
+	let file = BufWriter::new(File::create(path)?);
+	let mut encoder = png::Encoder::new(file, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+	encoder.set_color(png::ColorType::Rgb);
+	encoder.set_depth(png::BitDepth::Eight);
+	let mut writer = encoder.write_header()?;
+	writer.write_image_data(&pixels)?;
+	Ok(())
+}
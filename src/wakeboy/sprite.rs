@@ -0,0 +1,54 @@
+/// Bytes per OAM entry
+pub const SPRITE_SIZE: usize = 4;
+/// OAM positions are offset so sprites can be partly off the top left of the screen
+const SPRITE_X_OFFSET: i16 = 8;
+const SPRITE_Y_OFFSET: i16 = 16;
+
+const SPRITE_PALETTE: u8 = 0b0001_0000;
+const SPRITE_X_FLIP: u8 = 0b0010_0000;
+const SPRITE_Y_FLIP: u8 = 0b0100_0000;
+/// Background colors 1-3 are drawn over the sprite
+const SPRITE_BEHIND_BG: u8 = 0b1000_0000;
+
+/// One OAM entry, with its position in screen coordinates
+#[derive(Copy, Clone, Debug)]
+pub struct Sprite {
+	pub x: i16,
+	pub y: i16,
+	pub tile: u8,
+	pub attributes: u8,
+}
+
+impl Sprite {
+	pub fn from_oam(oam: &[u8], index: usize) -> Self {
+		let entry = &oam[index * SPRITE_SIZE..(index + 1) * SPRITE_SIZE];
+		Sprite {
+			y: entry[0] as i16 - SPRITE_Y_OFFSET,
+			x: entry[1] as i16 - SPRITE_X_OFFSET,
+			tile: entry[2],
+			attributes: entry[3],
+		}
+	}
+
+	pub fn uses_obp1(&self) -> bool {
+		self.attributes & SPRITE_PALETTE != 0
+	}
+
+	pub fn x_flip(&self) -> bool {
+		self.attributes & SPRITE_X_FLIP != 0
+	}
+
+	pub fn y_flip(&self) -> bool {
+		self.attributes & SPRITE_Y_FLIP != 0
+	}
+
+	pub fn behind_bg(&self) -> bool {
+		self.attributes & SPRITE_BEHIND_BG != 0
+	}
+
+	/// Color index of column `x` of a tile row, counting the X flip
+	pub fn row_color(&self, low: u8, high: u8, x: usize) -> u8 {
+		let bit = if self.x_flip() { x } else { 7 - x };
+		((high >> bit) & 1) << 1 | ((low >> bit) & 1)
+	}
+}
@@ -0,0 +1,248 @@
+use super::interrupts::*;
+
+pub const DIV:	usize = 0xFF04;
+pub const TIMA:	usize = 0xFF05;
+pub const TMA:	usize = 0xFF06;
+pub const TAC:	usize = 0xFF07;
+
+const TAC_ENABLE: u8 = 0b100;
+
+/// DIV, TIMA, TMA and TAC, clocked from the 16-bit system counter
+#[derive(Default)]
+pub struct Timer {
+	/// DIV is the upper byte of this counter, which runs at the clock speed
+	counter: u16,
+	tima: u8,
+	tma: u8,
+	tac: u8,
+	/// TIMA overflowed and reads as 0 until TMA gets loaded on the next M-cycle
+	reload_pending: bool,
+	/// TMA was loaded into TIMA during the current M-cycle
+	reloading: bool,
+}
+
+impl Timer {
+	/// Advances the timer by one M-cycle (4 clock ticks)
+	pub fn tick(&mut self, interrupts: &mut InterruptController) {
+		self.reloading = false;
+		if self.reload_pending {
+			self.reload_pending = false;
+			self.reloading = true;
+			self.tima = self.tma;
+			interrupts.request(Interrupt::Timer);
+		}
+
+		let old_counter = self.counter;
+		self.counter = self.counter.wrapping_add(4);
+		self.check_falling_edge(old_counter, self.tac);
+	}
+
+	/// Used to reproduce the state the boot rom leaves the timer in
+	pub fn set_counter(&mut self, counter: u16) {
+		self.counter = counter;
+	}
+
+	pub fn read(&self, address: usize) -> u8 {
+		match address {
+			DIV => (self.counter >> 8) as u8,
+			TIMA => self.tima,
+			TMA => self.tma,
+			TAC => self.tac,
+			_ => 0xFF,
+		}
+	}
+
+	pub fn write(&mut self, address: usize, data: u8) {
+		match address {
+			DIV => {
+				// Resetting the counter can drop the selected bit from 1 to 0,
+				// which TIMA sees as a falling edge
+				let old_counter = self.counter;
+				self.counter = 0;
+				self.check_falling_edge(old_counter, self.tac);
+			},
+			// Ignored while TMA is being loaded, cancels a pending reload otherwise
+			TIMA if !self.reloading => {
+				self.tima = data;
+				self.reload_pending = false;
+			},
+			TMA => {
+				self.tma = data;
+				if self.reloading {
+					self.tima = data;
+				}
+			},
+			TAC => {
+				let old_tac = self.tac;
+				self.tac = data;
+				self.check_falling_edge(self.counter, old_tac);
+			},
+			_ => {},
+		}
+	}
+
+	// TIMA is clocked by the selected counter bit ANDed with the enable bit,
+	// so changing either the counter or TAC can produce an extra increment
+	fn check_falling_edge(&mut self, old_counter: u16, old_tac: u8) {
+		if Self::signal(old_counter, old_tac) && !Self::signal(self.counter, self.tac) {
+			self.increment_tima();
+		}
+	}
+
+	fn signal(counter: u16, tac: u8) -> bool {
+		let bit = match tac & 0b11 {
+			0b00 => 9,
+			0b01 => 3,
+			0b10 => 5,
+			_ => 7,
+		};
+		tac & TAC_ENABLE != 0 && (counter >> bit) & 1 != 0
+	}
+
+	fn increment_tima(&mut self) {
+		let (tima, overflow) = self.tima.overflowing_add(1);
+		self.tima = tima;
+		if overflow {
+			self.reload_pending = true;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// TAC enabled with TIMA clocked every 4 M-cycles, on bit 3 of the counter
+	const TAC_FAST: u8 = TAC_ENABLE | 0b01;
+
+	fn run(timer: &mut Timer, interrupts: &mut InterruptController, cycles: u32) {
+		for _ in 0..cycles {
+			timer.tick(interrupts);
+		}
+	}
+
+	fn timer_irq(interrupts: &InterruptController) -> bool {
+		interrupts.read_flag() & Interrupt::Timer.bit() != 0
+	}
+
+	/// TIMA at 0xFF, one M-cycle away from overflowing
+	fn about_to_overflow(interrupts: &mut InterruptController) -> Timer {
+		let mut timer: Timer = Default::default();
+		timer.write(TMA, 0x20);
+		timer.write(TAC, TAC_FAST);
+		timer.write(TIMA, 0xFF);
+		run(&mut timer, interrupts, 3);
+		timer
+	}
+
+	#[test]
+	fn div_is_the_counter_upper_byte() {
+		let mut interrupts: InterruptController = Default::default();
+		let mut timer: Timer = Default::default();
+		run(&mut timer, &mut interrupts, 64);
+		assert_eq!(timer.read(DIV), 1);
+		timer.write(DIV, 0x42);
+		assert_eq!(timer.read(DIV), 0);
+	}
+
+	#[test]
+	fn tima_counts_on_falling_edges_of_the_selected_bit() {
+		let rates = [(0b00, 256), (0b01, 4), (0b10, 16), (0b11, 64)];
+		for (select, cycles) in rates {
+			let mut interrupts: InterruptController = Default::default();
+			let mut timer: Timer = Default::default();
+			timer.write(TAC, TAC_ENABLE | select);
+			run(&mut timer, &mut interrupts, cycles - 1);
+			assert_eq!(timer.read(TIMA), 0, "TAC {:#04x}", select);
+			run(&mut timer, &mut interrupts, 1);
+			assert_eq!(timer.read(TIMA), 1, "TAC {:#04x}", select);
+		}
+	}
+
+	#[test]
+	fn tima_stops_when_disabled() {
+		let mut interrupts: InterruptController = Default::default();
+		let mut timer: Timer = Default::default();
+		timer.write(TAC, 0b01);
+		run(&mut timer, &mut interrupts, 64);
+		assert_eq!(timer.read(TIMA), 0);
+	}
+
+	#[test]
+	fn overflow_reads_zero_for_a_cycle_before_the_reload() {
+		let mut interrupts: InterruptController = Default::default();
+		let mut timer = about_to_overflow(&mut interrupts);
+		run(&mut timer, &mut interrupts, 1);
+		assert_eq!(timer.read(TIMA), 0);
+		assert!(!timer_irq(&interrupts));
+
+		run(&mut timer, &mut interrupts, 1);
+		assert_eq!(timer.read(TIMA), 0x20);
+		assert!(timer_irq(&interrupts));
+	}
+
+	#[test]
+	fn tima_write_cancels_a_pending_reload() {
+		let mut interrupts: InterruptController = Default::default();
+		let mut timer = about_to_overflow(&mut interrupts);
+		run(&mut timer, &mut interrupts, 1);
+		timer.write(TIMA, 0x55);
+		run(&mut timer, &mut interrupts, 1);
+		assert_eq!(timer.read(TIMA), 0x55);
+		assert!(!timer_irq(&interrupts));
+	}
+
+	#[test]
+	fn tima_write_during_the_reload_is_ignored() {
+		let mut interrupts: InterruptController = Default::default();
+		let mut timer = about_to_overflow(&mut interrupts);
+		run(&mut timer, &mut interrupts, 2);
+		timer.write(TIMA, 0x55);
+		assert_eq!(timer.read(TIMA), 0x20);
+	}
+
+	#[test]
+	fn tma_write_during_the_reload_reaches_tima() {
+		let mut interrupts: InterruptController = Default::default();
+		let mut timer = about_to_overflow(&mut interrupts);
+		run(&mut timer, &mut interrupts, 2);
+		timer.write(TMA, 0x33);
+		assert_eq!(timer.read(TIMA), 0x33);
+
+		// Once the reload cycle is over, TMA is only used on the next overflow
+		run(&mut timer, &mut interrupts, 1);
+		timer.write(TMA, 0x44);
+		assert_eq!(timer.read(TIMA), 0x33);
+	}
+
+	#[test]
+	fn div_write_with_the_selected_bit_set_increments_tima() {
+		let mut interrupts: InterruptController = Default::default();
+		let mut timer: Timer = Default::default();
+		timer.write(TAC, TAC_FAST);
+		run(&mut timer, &mut interrupts, 2);
+		timer.write(DIV, 0);
+		assert_eq!(timer.read(TIMA), 1);
+
+		// Bit 3 is clear again, nothing falls
+		run(&mut timer, &mut interrupts, 1);
+		timer.write(DIV, 0);
+		assert_eq!(timer.read(TIMA), 1);
+	}
+
+	#[test]
+	fn tac_write_with_the_selected_bit_set_increments_tima() {
+		let mut interrupts: InterruptController = Default::default();
+		let mut timer: Timer = Default::default();
+		timer.write(TAC, TAC_FAST);
+		run(&mut timer, &mut interrupts, 2);
+		// Disabling the timer drops the signal
+		timer.write(TAC, 0b01);
+		assert_eq!(timer.read(TIMA), 1);
+
+		// So does selecting a bit that is clear
+		timer.write(TAC, TAC_FAST);
+		timer.write(TAC, TAC_ENABLE);
+		assert_eq!(timer.read(TIMA), 2);
+	}
+}